@@ -1,4 +1,6 @@
+use std::collections::VecDeque;
 use std::error::Error;
+use std::sync::Mutex;
 use std::{str::FromStr, sync::Arc};
 
 use amazon_qldb_driver::{retry, QldbDriver, QldbDriverBuilder, QldbResult, QldbSession};
@@ -22,12 +24,18 @@ use aws_smithy_client::erase::DynConnector;
 use aws_smithy_client::Client;
 use aws_smithy_http::{middleware::MapRequest, operation};
 use aws_smithy_http_tower::map_request::MapRequestLayer;
+use aws_types::SdkConfig;
 use http::header::HeaderName;
 use http::{HeaderValue, Uri};
 use tower::ServiceBuilder;
 
+use crate::qldb_hash::QldbHash;
 use crate::{error, settings::Environment};
 
+/// How many commit digests are kept for transactions the shell hasn't
+/// reported (yet), e.g. those committed by `Runner::query`.
+const MAX_COMMIT_DIGESTS: usize = 16;
+
 #[derive(Clone, Debug)]
 struct UserAgent;
 
@@ -57,15 +65,55 @@ pub(crate) struct QldbSessionSdk<C = DynConnector> {
 struct QldbSessionSdkInner<C = DynConnector> {
     client: Client<C>,
     conf: Config,
+    commit_digests: CommitDigests,
 }
 
 impl<C> QldbSessionSdk<C> {
     fn new(client: Client<C>, conf: Config) -> QldbSessionSdk<C> {
-        let inner = QldbSessionSdkInner { client, conf };
+        let inner = QldbSessionSdkInner {
+            client,
+            conf,
+            commit_digests: CommitDigests::default(),
+        };
         QldbSessionSdk {
             inner: Arc::new(inner),
         }
     }
+
+    pub(crate) fn commit_digests(&self) -> CommitDigests {
+        self.inner.commit_digests.clone()
+    }
+}
+
+/// The commit digests QLDB returned, by transaction id. The driver doesn't
+/// expose the CommitTransaction response, so the client records them as they
+/// go past.
+#[derive(Clone, Default)]
+pub(crate) struct CommitDigests {
+    digests: Arc<Mutex<VecDeque<(String, QldbHash)>>>,
+}
+
+impl CommitDigests {
+    fn record(&self, output: &SendCommandOutput) {
+        let commit = match output.commit_transaction {
+            Some(ref commit) => commit,
+            None => return,
+        };
+        if let (Some(id), Some(digest)) = (&commit.transaction_id, &commit.commit_digest) {
+            let mut digests = self.digests.lock().unwrap();
+            if digests.len() == MAX_COMMIT_DIGESTS {
+                digests.pop_front();
+            }
+            digests.push_back((id.clone(), QldbHash::from_bytes(digest.as_ref().to_vec())));
+        }
+    }
+
+    /// The digest QLDB returned when `transaction_id` was committed.
+    pub(crate) fn take(&self, transaction_id: &str) -> Option<QldbHash> {
+        let mut digests = self.digests.lock().unwrap();
+        let index = digests.iter().position(|(id, _)| id == transaction_id)?;
+        digests.remove(index).map(|(_, digest)| digest)
+    }
 }
 
 #[async_trait]
@@ -81,14 +129,18 @@ where
             .make_operation(&self.inner.conf)
             .await
             .expect("valid operation"); // FIXME: remove potential panic
-        self.inner.client.call(op).await
+        let output = self.inner.client.call(op).await;
+        if let Ok(ref output) = output {
+            self.inner.commit_digests.record(output);
+        }
+        output
     }
 }
 
-pub(crate) async fn build_driver(
-    client: QldbSessionSdk,
-    ledger: String,
-) -> QldbResult<QldbDriver<QldbSessionSdk>> {
+pub(crate) async fn build_driver<C>(client: C, ledger: String) -> QldbResult<QldbDriver<C>>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
     // We disable transaction retries because they don't make sense. Users
     // are entering statements, so if the tx fails they actually have to
     // enter them again! We can't simply remember their inputs and try
@@ -177,20 +229,8 @@ async fn build_client(env: &Environment) -> Result<QldbSessionSdk<DynConnector>>
         })
         .build_dyn();
 
-    let aws_config = aws_config::from_env();
-    let aws_config = match env.current_ledger().profile {
-        Some(ref name) => aws_config.credentials_provider(
-            LazyCachingCredentialsProvider::builder()
-                .load(
-                    ProfileFileCredentialsProvider::builder()
-                        .profile_name(name)
-                        .build(),
-                )
-                .build(),
-        ),
-        None => aws_config,
-    };
-    let aws_config = aws_config.load().await;
+    let profile = env.current_ledger().profile.clone();
+    let aws_config = load_aws_config(profile.as_deref()).await;
 
     let conf = config::Builder::from(&aws_config).region(env.current_region());
     let conf = match env.current_ledger().qldb_session_endpoint {
@@ -210,6 +250,25 @@ async fn build_client(env: &Environment) -> Result<QldbSessionSdk<DynConnector>>
     Ok(QldbSessionSdk::new(client, conf.build()))
 }
 
+/// Loads credentials (from `profile`, if given) and other settings from the
+/// environment. Shared by the session and control plane clients.
+pub(crate) async fn load_aws_config(profile: Option<&str>) -> SdkConfig {
+    let aws_config = aws_config::from_env();
+    let aws_config = match profile {
+        Some(name) => aws_config.credentials_provider(
+            LazyCachingCredentialsProvider::builder()
+                .load(
+                    ProfileFileCredentialsProvider::builder()
+                        .profile_name(name)
+                        .build(),
+                )
+                .build(),
+        ),
+        None => aws_config,
+    };
+    aws_config.load().await
+}
+
 // Note: infallible, but potentially fallible in the future (e.g. if we want to
 // check that the region is valid).
 pub(crate) async fn determine_region<S>(user_specified: Option<S>) -> Result<Region>
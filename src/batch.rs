@@ -0,0 +1,242 @@
+use amazon_qldb_driver::QldbSession;
+use anyhow::{anyhow, Result};
+
+use crate::runner::{Runner, TickFlow};
+use crate::transaction::TransactionKind;
+use crate::QldbShellError;
+
+/// QLDB rejects transactions that modify more documents than this.
+pub(crate) const MAX_DOCUMENTS_PER_TRANSACTION: usize = 40;
+
+/// Tracks how far through a script we are, so that failures can be reported
+/// precisely. Statements are numbered from 1.
+#[derive(Debug, Default)]
+pub(crate) struct BatchProgress {
+    /// Statements run so far, including those in the open batch.
+    statements: usize,
+    /// Statements that are part of batches which have been committed.
+    committed: usize,
+    batches: usize,
+}
+
+/// Whether the open batch has to be committed before the next statement runs.
+fn is_full(
+    statements: usize,
+    documents: usize,
+    batch_size: usize,
+    single_transaction: bool,
+) -> bool {
+    !single_transaction && (statements >= batch_size || documents >= MAX_DOCUMENTS_PER_TRANSACTION)
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// The maximum number of statements per transaction if statements outside
+    /// of a transaction are being grouped together (see `--batch-size` and
+    /// `--single-transaction`).
+    pub(crate) fn batch_size(&self) -> Option<usize> {
+        let config = self.deps.env.config();
+        if config.ui.single_transaction {
+            Some(usize::MAX)
+        } else {
+            config.ui.batch_size
+        }
+    }
+
+    pub(crate) fn in_batch(&self) -> bool {
+        match self.current_transaction {
+            Some(ref tx) => tx.kind == TransactionKind::Batch,
+            None => false,
+        }
+    }
+
+    /// Runs `line` in the open batch, starting a new one if there is none or
+    /// the open one is full. Batches are also considered full once they have
+    /// modified as many documents as QLDB allows in one transaction, so that
+    /// the next statement doesn't push them over the limit. With
+    /// `--single-transaction` the batch is never split: a script that modifies
+    /// too many documents fails as a whole instead.
+    ///
+    /// Any failure rolls back the open batch. Processing stops, as later
+    /// statements may depend on the ones that were rolled back.
+    pub(crate) async fn handle_batched_partiql(
+        &mut self,
+        line: &str,
+        batch_size: usize,
+    ) -> Result<TickFlow> {
+        let single_transaction = self.deps.env.config().ui.single_transaction;
+        let full = match self.current_transaction {
+            Some(ref tx) => is_full(
+                tx.statements.len(),
+                tx.documents_modified(),
+                batch_size,
+                single_transaction,
+            ),
+            None => false,
+        };
+        if full {
+            self.commit_batch().await?;
+        }
+
+        if self.current_transaction.is_none() {
+            self.start_transaction(TransactionKind::Batch).await?;
+            self.batch_progress.batches += 1;
+        }
+
+        self.batch_progress.statements += 1;
+        if let Err(e) = self.handle_partiql(line).await {
+            Err(self.fail_batch(e).await)?
+        }
+
+        let documents = match self.current_transaction {
+            Some(ref tx) => tx.documents_modified(),
+            None => 0,
+        };
+        if single_transaction && documents > MAX_DOCUMENTS_PER_TRANSACTION {
+            let e = anyhow!(
+                "the script modified {} documents, but QLDB allows at most {} per transaction. \
+                Run it without --single-transaction to commit it in batches",
+                documents,
+                MAX_DOCUMENTS_PER_TRANSACTION
+            );
+            Err(self.fail_batch(e).await)?
+        }
+
+        Ok(TickFlow::Again)
+    }
+
+    /// Commits the open batch, if there is one. Like any commit, this
+    /// refreshes the completion catalog if the batch created or dropped
+    /// tables.
+    pub(crate) async fn commit_batch(&mut self) -> Result<()> {
+        if !self.in_batch() {
+            return Ok(());
+        }
+
+        if let Err(e) = self.handle_commit().await {
+            Err(self.fail_batch(e).await)?
+        }
+        self.batch_progress.committed = self.batch_progress.statements;
+        Ok(())
+    }
+
+    /// Rolls back the open batch (if it is still open) and describes where
+    /// the script failed.
+    pub(crate) async fn fail_batch(&mut self, cause: anyhow::Error) -> QldbShellError {
+        let documents = match self.current_transaction {
+            Some(ref tx) => tx.documents_modified(),
+            None => 0,
+        };
+        if self.in_batch() {
+            let _ = self.handle_abort().await;
+        }
+        self.deps.ui.clear_pending();
+
+        let single_transaction = self.deps.env.config().ui.single_transaction;
+        QldbShellError::BatchFailed(failure_message(
+            &self.batch_progress,
+            single_transaction,
+            &cause,
+            documents,
+        ))
+    }
+}
+
+/// Describes a batch that failed with `cause`, and what was rolled back.
+/// `documents` is how many documents the rolled back transaction had
+/// modified.
+fn failure_message(
+    progress: &BatchProgress,
+    single_transaction: bool,
+    cause: &anyhow::Error,
+    documents: usize,
+) -> String {
+    let mut message = format!("statement {} failed: {}\n", progress.statements, cause);
+    if single_transaction {
+        message.push_str(&format!(
+            "rolled back the whole script (statements 1-{}), nothing was committed",
+            progress.statements
+        ));
+    } else {
+        message.push_str(&format!(
+            "rolled back batch {} (statements {}-{}), {} statement(s) were committed before it",
+            progress.batches,
+            progress.committed + 1,
+            progress.statements,
+            progress.committed
+        ));
+    }
+    if documents > 0 {
+        message.push_str(&format!(
+                "\nthe rolled back transaction had modified {} document(s), QLDB allows at most {} per transaction",
+                documents, MAX_DOCUMENTS_PER_TRANSACTION
+            ));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a script of `statements` inserts (of one document each) through
+    /// the batching rules, returning how many documents were committed before
+    /// the script failed (if it did).
+    fn run(statements: usize, batch_size: usize, single_transaction: bool) -> (usize, bool) {
+        let (mut committed, mut open, mut open_documents) = (0, 0, 0);
+        for _ in 0..statements {
+            if is_full(open, open_documents, batch_size, single_transaction) {
+                committed += open_documents;
+                open = 0;
+                open_documents = 0;
+            }
+            open += 1;
+            open_documents += 1;
+            if open_documents > MAX_DOCUMENTS_PER_TRANSACTION {
+                return (committed, true);
+            }
+        }
+        (committed + open_documents, false)
+    }
+
+    #[test]
+    fn single_transaction_is_never_split() {
+        assert_eq!((0, true), run(41, usize::MAX, true));
+        assert_eq!((40, false), run(40, usize::MAX, true));
+
+        // Batches are committed at the document limit instead.
+        assert_eq!((41, false), run(41, 100, false));
+        assert!(is_full(40, 0, 40, false));
+        assert!(is_full(1, 40, 40, false));
+        assert!(!is_full(usize::MAX, 40, usize::MAX, true));
+    }
+
+    #[test]
+    fn expired_batches_fail_the_script() {
+        let expired: anyhow::Error = QldbShellError::TransactionExpired {
+            id: "tx1".to_string(),
+            age_secs: 31,
+        }
+        .into();
+        let progress = BatchProgress {
+            statements: 5,
+            committed: 0,
+            batches: 1,
+        };
+        let message = failure_message(&progress, true, &expired, 0);
+        assert!(message.starts_with("statement 5 failed: Transaction tx1"));
+        assert!(message
+            .ends_with("rolled back the whole script (statements 1-5), nothing was committed"));
+
+        let progress = BatchProgress {
+            statements: 7,
+            committed: 4,
+            batches: 2,
+        };
+        assert!(failure_message(&progress, false, &expired, 0).ends_with(
+            "rolled back batch 2 (statements 5-7), 4 statement(s) were committed before it"
+        ));
+    }
+}
@@ -0,0 +1,125 @@
+//! What tab completion needs to know about the backslash commands.
+//!
+//! clap 2 keeps the definitions an `App` is built from in `#[doc(hidden)]`
+//! fields, which can change in any release, so clap is pinned to an exact
+//! version in Cargo.toml. This is the only place that reads them, and its
+//! test fails if they stop holding what completion expects.
+
+use structopt::clap::App;
+
+/// A (sub)command of [`Backslash`](super::Backslash).
+pub(crate) struct CommandDefinition<'c> {
+    app: &'c App<'static, 'static>,
+}
+
+/// An option or positional argument.
+#[derive(Debug)]
+pub(crate) struct ArgumentDefinition {
+    pub(crate) name: &'static str,
+    /// Empty unless clap checks the value.
+    pub(crate) possible_values: Vec<&'static str>,
+}
+
+impl<'c> CommandDefinition<'c> {
+    pub(crate) fn new(app: &'c App<'static, 'static>) -> Self {
+        CommandDefinition { app }
+    }
+
+    pub(crate) fn subcommand(&self, name: &str) -> Option<CommandDefinition<'c>> {
+        self.app
+            .p
+            .subcommands
+            .iter()
+            .find(|s| s.p.meta.name == name)
+            .map(CommandDefinition::new)
+    }
+
+    pub(crate) fn subcommand_names(&self) -> Vec<String> {
+        self.app
+            .p
+            .subcommands
+            .iter()
+            .map(|s| s.p.meta.name.clone())
+            .collect()
+    }
+
+    /// The option (an argument that takes a value) called `--long`.
+    pub(crate) fn option_by_long(&self, long: &str) -> Option<ArgumentDefinition> {
+        let opt = self.app.p.opts.iter().find(|o| o.s.long == Some(long))?;
+        Some(ArgumentDefinition {
+            name: opt.b.name,
+            possible_values: opt.v.possible_vals.clone().unwrap_or_default(),
+        })
+    }
+
+    /// The option (an argument that takes a value) called `-short`.
+    pub(crate) fn option_by_short(&self, short: &str) -> Option<ArgumentDefinition> {
+        let opt = self
+            .app
+            .p
+            .opts
+            .iter()
+            .find(|o| o.s.short.map(|s| s.to_string()).as_deref() == Some(short))?;
+        Some(ArgumentDefinition {
+            name: opt.b.name,
+            possible_values: opt.v.possible_vals.clone().unwrap_or_default(),
+        })
+    }
+
+    /// The `--long` names of the flags and options.
+    pub(crate) fn long_names(&self) -> Vec<String> {
+        let flags = self.app.p.flags.iter().filter_map(|f| f.s.long);
+        let opts = self.app.p.opts.iter().filter_map(|o| o.s.long);
+        flags.chain(opts).map(|l| format!("--{}", l)).collect()
+    }
+
+    /// The `n`th positional argument, counting from 0.
+    pub(crate) fn positional(&self, n: usize) -> Option<ArgumentDefinition> {
+        let positional = self.app.p.positionals.values().nth(n)?;
+        Some(ArgumentDefinition {
+            name: positional.b.name,
+            possible_values: positional.v.possible_vals.clone().unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Backslash;
+    use structopt::StructOpt;
+
+    #[test]
+    fn reads_clap_definitions() {
+        let app = Backslash::clap();
+        let backslash = CommandDefinition::new(&app);
+        assert!(backslash.subcommand_names().contains(&"export".to_string()));
+        assert!(backslash.subcommand("nope").is_none());
+
+        let export = backslash.subcommand("export").expect("no \\export");
+        assert_eq!(
+            vec!["source", "path"],
+            (0..3)
+                .filter_map(|n| export.positional(n))
+                .map(|p| p.name)
+                .collect::<Vec<_>>()
+        );
+        let format = export.option_by_long("format").expect("no --format");
+        assert_eq!("format", format.name);
+        assert!(format.possible_values.contains(&"csv"));
+        assert!(export.option_by_long("source").is_none());
+
+        let using = backslash.subcommand("use").expect("no \\use");
+        let ledger = using.option_by_short("l").expect("no -l");
+        assert_eq!("ledger", ledger.name);
+        assert!(ledger.possible_values.is_empty());
+        assert!(using.long_names().contains(&"--region".to_string()));
+
+        let delete = backslash
+            .subcommand("ledger")
+            .and_then(|l| l.subcommand("delete"))
+            .expect("no \\ledger delete");
+        assert!(delete.long_names().contains(&"--yes".to_string()));
+        assert!(delete.option_by_long("yes").is_none());
+    }
+}
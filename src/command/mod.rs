@@ -1,11 +1,19 @@
 use anyhow::Result;
 use std::convert::TryFrom;
 use std::ffi::OsString;
+use std::path::PathBuf;
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 use url::Url;
 
+use crate::import::FieldType;
+use crate::results::ExportFormat;
 use crate::settings::config::EditMode;
+use crate::settings::FormatMode;
+
+mod definition;
+
+pub(crate) use definition::{ArgumentDefinition, CommandDefinition};
 
 pub fn backslash<I>(iter: I) -> Result<Backslash>
 where
@@ -22,6 +30,86 @@ where
 pub enum Backslash {
     Set(SetCommand),
     Use(UseCommand),
+    Tx(TxCommand),
+    /// Abort every transaction instead of committing it.
+    Dryrun(OnOff),
+    /// Create and manage ledgers.
+    Ledger(LedgerCommand),
+    Show(ShowCommand),
+    /// Describes a table: its id, status and indexes.
+    Describe {
+        table: String,
+        /// Also summarize the fields of (up to) this many documents. Only the
+        /// pages needed for them are read.
+        #[structopt(long)]
+        sample: Option<usize>,
+    },
+    /// Shows every revision of a document, as a diff against the revision
+    /// before it.
+    HistoryOf {
+        table: String,
+        document_id: String,
+        /// Only show revisions committed at or after this Ion timestamp.
+        #[structopt(long)]
+        from: Option<String>,
+        /// Only show revisions committed before this Ion timestamp.
+        #[structopt(long)]
+        to: Option<String>,
+    },
+    /// Compares two Ion values structurally.
+    Diff(DiffCommand),
+    /// Verifies the latest revision of a document against the ledger's
+    /// digest.
+    Verify {
+        table: String,
+        document_id: String,
+    },
+    /// Checks the hash of every revision returned by the last statement.
+    Hash,
+    /// Writes every document of a table, or the results of a query, to a
+    /// file. An interrupted export is resumed by running it again.
+    Export {
+        /// A table name or a PartiQL query.
+        source: String,
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(long, default_value = "ion", possible_values = EXPORT_FORMATS, case_insensitive = true)]
+        format: ExportFormat,
+    },
+    /// Inserts the documents in a file into a table, in batches.
+    Import(ImportCommand),
+}
+
+/// The names `ExportFormat::from_str` accepts.
+const EXPORT_FORMATS: &[&str] = &["ion", "ion-text", "ion-binary", "jsonl", "json", "csv"];
+
+#[derive(StructOpt, Debug)]
+pub enum DiffCommand {
+    /// Compares two revisions of a document, by version.
+    Revisions {
+        table: String,
+        document_id: String,
+        old_version: i64,
+        new_version: i64,
+    },
+    /// Compares two documents, by id.
+    Documents {
+        table: String,
+        old_id: String,
+        new_id: String,
+    },
+    /// Compares the documents returned by the last two statements.
+    Results,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ShowCommand {
+    /// Lists the active tables in the current ledger.
+    Tables,
+    /// Lists the ledgers in the current region.
+    Ledgers,
+    /// Lists the indexes of a table, or of every active table.
+    Indexes { table: Option<String> },
 }
 
 #[derive(StructOpt, Debug, Clone)]
@@ -39,10 +127,168 @@ impl From<&TrueFalse> for bool {
     }
 }
 
+/// Splits a command line into arguments. Arguments are separated by
+/// whitespace, unless they are wrapped in double quotes (e.g. `\set prompt
+/// "qldb $LEDGER> "`).
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(arg) = current {
+        args.push(arg);
+    }
+
+    args
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub enum OnOff {
+    On,
+    Off,
+}
+
+impl From<&OnOff> for bool {
+    fn from(oo: &OnOff) -> Self {
+        match oo {
+            OnOff::On => true,
+            OnOff::Off => false,
+        }
+    }
+}
+
 #[derive(StructOpt, Debug)]
 pub enum SetCommand {
     EditMode(EditMode),
     TerminatorRequired(TrueFalse),
+    Format(FormatMode),
+    AutoCommit(TrueFalse),
+    DisplayQueryMetrics(TrueFalse),
+    DisplayCtrlSignals(TrueFalse),
+    /// Check the hash of every revision a query returns.
+    VerifyHashes(TrueFalse),
+    /// Sets the prompt template. Without a value, the default prompt is restored.
+    Prompt {
+        template: Option<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum TxCommand {
+    /// Lists every statement run in the current transaction.
+    Log,
+    /// Lists every open transaction.
+    List,
+    /// Switches to another open transaction.
+    Use {
+        /// The name the transaction was started with (`begin as NAME`), or
+        /// its id.
+        name: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum LedgerCommand {
+    /// Creates a ledger.
+    Create {
+        name: String,
+        /// ALLOW_ALL or STANDARD. See the QLDB Developer Guide for details.
+        #[structopt(long, default_value = "STANDARD", possible_values = &["ALLOW_ALL", "STANDARD"], case_insensitive = true)]
+        permissions_mode: String,
+        /// Prevent the ledger from being deleted until this is turned off.
+        #[structopt(long)]
+        deletion_protection: bool,
+        /// Tags to add to the ledger, as KEY=VALUE.
+        #[structopt(long = "--tag", parse(try_from_str = parse_tag))]
+        tags: Vec<(String, String)>,
+        /// Wait until the ledger is active.
+        #[structopt(long)]
+        wait: bool,
+    },
+    /// Describes a ledger (by default, the current one).
+    Describe { name: Option<String> },
+    /// Changes the permissions mode or deletion protection of a ledger (by
+    /// default, the current one).
+    Update {
+        name: Option<String>,
+        #[structopt(long, possible_values = &["ALLOW_ALL", "STANDARD"], case_insensitive = true)]
+        permissions_mode: Option<String>,
+        /// true or false.
+        #[structopt(long)]
+        deletion_protection: Option<bool>,
+    },
+    /// Deletes a ledger. Its name must be given, even if it is the current
+    /// ledger.
+    Delete {
+        name: String,
+        /// Don't ask for the name to be typed again to confirm.
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Lists, adds or removes the tags of a ledger (by default, the current
+    /// one).
+    Tags {
+        name: Option<String>,
+        /// Tags to add, as KEY=VALUE.
+        #[structopt(long = "--add", parse(try_from_str = parse_tag))]
+        add: Vec<(String, String)>,
+        /// Keys of tags to remove.
+        #[structopt(long = "--remove")]
+        remove: Vec<String>,
+    },
+}
+
+fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{}' should be KEY=VALUE", tag)),
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ImportCommand {
+    #[structopt(parse(from_os_str))]
+    pub path: PathBuf,
+    #[structopt(name = "into", possible_values = &["into"])]
+    _into: String,
+    pub table: String,
+    /// By default, this is guessed from the file's extension.
+    #[structopt(long, possible_values = EXPORT_FORMATS, case_insensitive = true)]
+    pub format: Option<ExportFormat>,
+    /// The maximum number of documents inserted per transaction.
+    #[structopt(long, default_value = "40")]
+    pub batch: usize,
+    /// Converts a (top-level) field to a type, as FIELD=TYPE.
+    #[structopt(long = "--type", parse(try_from_str = parse_field_type))]
+    pub types: Vec<(String, FieldType)>,
+    /// Skips this many documents, e.g. those imported before a failure.
+    #[structopt(long, default_value = "0")]
+    pub skip: usize,
+    /// Only reads and converts the documents, without inserting them.
+    #[structopt(long)]
+    pub dry_run: bool,
+}
+
+fn parse_field_type(field_type: &str) -> Result<(String, FieldType), String> {
+    let (field, type_name) = parse_tag(field_type)?;
+    match type_name.parse() {
+        Ok(t) => Ok((field, t)),
+        Err(e) => Err(format!("{}", e)),
+    }
 }
 
 // FIXME: is there a way to share this with the main CLI opts?
@@ -74,4 +320,251 @@ mod command_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn set_format() -> Result<()> {
+        let backslash = super::backslash(&["set", "format", "table"])?;
+        if let Backslash::Set(SetCommand::Format(format)) = backslash {
+            assert!(matches!(format, FormatMode::Table));
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_prompt() -> Result<()> {
+        let backslash = super::backslash(split_args(r#"set prompt "$LEDGER> ""#))?;
+        if let Backslash::Set(SetCommand::Prompt { template }) = backslash {
+            assert_eq!(Some("$LEDGER> "), template.as_deref());
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn tx_use() -> Result<()> {
+        let backslash = super::backslash(split_args("tx use t1"))?;
+        if let Backslash::Tx(TxCommand::Use { name }) = backslash {
+            assert_eq!("t1", name);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ledger_create() -> Result<()> {
+        let backslash = super::backslash(split_args(
+            "ledger create my-ledger --permissions-mode allow_all --tag team=shell --wait",
+        ))?;
+        if let Backslash::Ledger(LedgerCommand::Create {
+            name,
+            permissions_mode,
+            deletion_protection,
+            tags,
+            wait,
+        }) = backslash
+        {
+            assert_eq!("my-ledger", name);
+            assert_eq!("allow_all", permissions_mode);
+            assert!(!deletion_protection);
+            assert_eq!(vec![("team".to_string(), "shell".to_string())], tags);
+            assert!(wait);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        let backslash = super::backslash(split_args("ledger update --deletion-protection false"))?;
+        if let Backslash::Ledger(LedgerCommand::Update {
+            name,
+            deletion_protection,
+            ..
+        }) = backslash
+        {
+            assert_eq!(None, name);
+            assert_eq!(Some(false), deletion_protection);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        let backslash = super::backslash(split_args("ledger delete prod --yes"))?;
+        if let Backslash::Ledger(LedgerCommand::Delete { name, yes }) = backslash {
+            assert_eq!("prod", name);
+            assert!(yes);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+        assert!(super::backslash(split_args("ledger delete")).is_err());
+
+        assert!(super::backslash(split_args("ledger tags --add nope")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn show_and_describe() -> Result<()> {
+        let backslash = super::backslash(split_args("show indexes Vehicle"))?;
+        if let Backslash::Show(ShowCommand::Indexes { table }) = backslash {
+            assert_eq!(Some("Vehicle"), table.as_deref());
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        let backslash = super::backslash(split_args("describe Vehicle --sample 10"))?;
+        if let Backslash::Describe { table, sample } = backslash {
+            assert_eq!("Vehicle", table);
+            assert_eq!(Some(10), sample);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn history_of() -> Result<()> {
+        let backslash = super::backslash(split_args(
+            "history-of Vehicle 8F0TPCmdNQ6JTRpiLj2TmW --from 2022-01-01T",
+        ))?;
+        if let Backslash::HistoryOf {
+            table,
+            document_id,
+            from,
+            to,
+        } = backslash
+        {
+            assert_eq!("Vehicle", table);
+            assert_eq!("8F0TPCmdNQ6JTRpiLj2TmW", document_id);
+            assert_eq!(Some("2022-01-01T"), from.as_deref());
+            assert_eq!(None, to);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff() -> Result<()> {
+        let backslash = super::backslash(split_args("diff revisions Vehicle abc 0 2"))?;
+        if let Backslash::Diff(DiffCommand::Revisions {
+            table,
+            document_id,
+            old_version,
+            new_version,
+        }) = backslash
+        {
+            assert_eq!("Vehicle", table);
+            assert_eq!("abc", document_id);
+            assert_eq!((0, 2), (old_version, new_version));
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        assert!(matches!(
+            super::backslash(split_args("diff results"))?,
+            Backslash::Diff(DiffCommand::Results)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn verify() -> Result<()> {
+        let backslash = super::backslash(split_args("verify Vehicle abc"))?;
+        if let Backslash::Verify { table, document_id } = backslash {
+            assert_eq!("Vehicle", table);
+            assert_eq!("abc", document_id);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn export() -> Result<()> {
+        let backslash = super::backslash(split_args("export Vehicle vehicles.ion"))?;
+        if let Backslash::Export {
+            source,
+            path,
+            format,
+        } = backslash
+        {
+            assert_eq!("Vehicle", source);
+            assert_eq!(PathBuf::from("vehicles.ion"), path);
+            assert_eq!(ExportFormat::Ion, format);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        let backslash = super::backslash(split_args(
+            r#"export "SELECT VIN FROM Vehicle" out.csv --format csv"#,
+        ))?;
+        if let Backslash::Export { source, format, .. } = backslash {
+            assert_eq!("SELECT VIN FROM Vehicle", source);
+            assert_eq!(ExportFormat::Csv, format);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn import() -> Result<()> {
+        let backslash = super::backslash(split_args(
+            "import vehicles.csv into Vehicle --type VIN=string --type Year=int --skip 80",
+        ))?;
+        if let Backslash::Import(command) = backslash {
+            assert_eq!(PathBuf::from("vehicles.csv"), command.path);
+            assert_eq!("Vehicle", command.table);
+            assert_eq!(None, command.format);
+            assert_eq!(40, command.batch);
+            assert_eq!(
+                vec![
+                    ("VIN".to_string(), FieldType::String),
+                    ("Year".to_string(), FieldType::Int)
+                ],
+                command.types
+            );
+            assert_eq!(80, command.skip);
+            assert!(!command.dry_run);
+        } else {
+            panic!("failure, parsed to: {:?}", backslash);
+        }
+
+        assert!(super::backslash(split_args("import vehicles.csv Vehicle")).is_err());
+        assert!(super::backslash(split_args("import a.csv into T --type VIN=text")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn split_args_with_quotes() {
+        assert_eq!(
+            vec!["set", "auto-commit", "true"],
+            split_args("set  auto-commit true")
+        );
+        assert_eq!(vec!["a b", "", "c"], split_args(r#""a b" "" c"#));
+    }
+
+    #[test]
+    fn export_format_names() -> Result<()> {
+        for name in EXPORT_FORMATS {
+            let upper = name.to_uppercase();
+            for name in &[*name, &upper[..]] {
+                let backslash = super::backslash(&["export", "Vehicle", "a", "--format", name])?;
+                if let Backslash::Export { format, .. } = backslash {
+                    assert_eq!(name.parse::<ExportFormat>()?, format);
+                } else {
+                    panic!("failure, parsed to: {:?}", backslash);
+                }
+            }
+        }
+        assert!(super::backslash(&["export", "Vehicle", "a", "--format", "xml"]).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,764 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use amazon_qldb_driver::{QldbSession, StatementResults};
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::{Element, Struct, SymbolToken};
+use structopt::StructOpt;
+
+use crate::command::{self, ArgumentDefinition, Backslash, CommandDefinition};
+use crate::results;
+use crate::runner::{self, Runner};
+
+/// Keywords of the PartiQL subset QLDB supports.
+const KEYWORDS: &[&str] = &[
+    "AND", "AS", "AT", "BETWEEN", "BY", "CREATE", "DELETE", "DISTINCT", "DROP", "EXISTS", "FROM",
+    "IN", "INDEX", "INSERT", "INTO", "IS", "JOIN", "LIKE", "MISSING", "NOT", "NULL", "ON", "OR",
+    "REMOVE", "SELECT", "SET", "TABLE", "UNDROP", "UPDATE", "VALUE", "WHERE",
+];
+
+/// Functions, completed with their opening parenthesis.
+const FUNCTIONS: &[&str] = &[
+    "avg",
+    "cast",
+    "char_length",
+    "coalesce",
+    "count",
+    "date_add",
+    "date_diff",
+    "exists",
+    "extract",
+    "lower",
+    "max",
+    "min",
+    "nullif",
+    "size",
+    "substring",
+    "sum",
+    "to_string",
+    "to_timestamp",
+    "trim",
+    "upper",
+    "utcnow",
+];
+
+/// The fields of the revisions in `history()` and `_ql_committed_` views.
+const REVISION_FIELDS: &[&str] = &["blockAddress", "hash", "data", "metadata"];
+const METADATA_FIELDS: &[&str] = &["id", "version", "txTime", "txId"];
+
+const COMMITTED_PREFIX: &str = "_ql_committed_";
+
+/// At most this many field names are remembered per table.
+const MAX_FIELDS: usize = 200;
+
+/// Fields are learned from (at most) this many documents of a statement's
+/// results.
+const LEARNED_DOCUMENTS: usize = 100;
+
+/// The regions QLDB is available in.
+const REGIONS: &[&str] = &[
+    "ap-northeast-1",
+    "ap-northeast-2",
+    "ap-southeast-1",
+    "ap-southeast-2",
+    "ca-central-1",
+    "eu-central-1",
+    "eu-west-1",
+    "eu-west-2",
+    "us-east-1",
+    "us-east-2",
+    "us-west-2",
+];
+
+/// What the completer knows about the ledger: its tables, and the fields
+/// seen in documents returned from them. `ledgers` are the ledgers in
+/// config.ion, for `\use --ledger`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Catalog {
+    pub(crate) tables: Vec<String>,
+    pub(crate) fields: HashMap<String, Vec<String>>,
+    pub(crate) ledgers: Vec<String>,
+}
+
+impl Catalog {
+    fn table(&self, name: &str) -> Option<&str> {
+        let name = name.strip_prefix(COMMITTED_PREFIX).unwrap_or(name);
+        self.tables
+            .iter()
+            .find(|t| t.as_str() == name)
+            .map(|t| t.as_str())
+    }
+
+    /// Remembers the top-level fields of `docs`, returned by `statement`,
+    /// if it is a `SELECT * FROM` a single table. Other statements return
+    /// something else than the table's documents: projections, aggregates
+    /// (`_1`) or, for DML, the `documentId`s. Revisions (from `history()` or
+    /// `_ql_committed_` views) contribute the fields of their data.
+    pub(crate) fn learn_fields(&mut self, statement: &str, docs: &[OwnedElement]) {
+        if !selects_documents(statement) {
+            return;
+        }
+        let tokens = tokens(statement);
+        let sources = sources(&tokens, self);
+        if let [source] = &sources[..] {
+            let table = source.table.clone();
+            self.learn_table_fields(&table, docs);
+        }
+    }
+
+    /// Remembers the top-level fields of `docs`, from `table`.
+    fn learn_table_fields(&mut self, table: &str, docs: &[OwnedElement]) {
+        let fields = self.fields.entry(table.to_string()).or_default();
+        for doc in docs.iter().take(LEARNED_DOCUMENTS) {
+            let strukt = match doc.as_struct() {
+                Some(strukt) => strukt,
+                None => continue,
+            };
+            let strukt = match (strukt.get("data"), strukt.get("metadata")) {
+                (Some(data), Some(_)) => match data.as_struct() {
+                    Some(data) => data,
+                    None => continue,
+                },
+                _ => strukt,
+            };
+            for (name, _) in strukt.iter() {
+                match name.text() {
+                    Some(name)
+                        if fields.len() < MAX_FIELDS && !fields.iter().any(|f| f == name) =>
+                    {
+                        fields.push(name.to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// The tables `statement` refers to that no fields were learned from yet,
+    /// not even none.
+    fn unsampled_tables(&self, statement: &str) -> Vec<String> {
+        let mut tables: Vec<String> = vec![];
+        for source in sources(&tokens(statement), self) {
+            if !self.fields.contains_key(&source.table) && !tables.contains(&source.table) {
+                tables.push(source.table);
+            }
+        }
+        tables
+    }
+}
+
+/// Whether `statement` starts with `SELECT * FROM`.
+fn selects_documents(statement: &str) -> bool {
+    let words: Vec<_> = statement.split_whitespace().take(3).collect();
+    match words[..] {
+        [select, "*", from] => is_keyword(select, "SELECT") && is_keyword(from, "FROM"),
+        _ => false,
+    }
+}
+
+/// A table a statement reads or writes, and whether it is read as revisions.
+#[derive(Debug, PartialEq)]
+struct Source {
+    table: String,
+    revisions: bool,
+}
+
+/// Splits a statement into words (identifiers, keywords and paths such as
+/// `v.Owner.Name`) and punctuation. Strings, quoted identifiers and Ion
+/// literals are skipped.
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                for next in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !is_word_char(next) && next != '.' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+            '(' | ',' => tokens.push(c.to_string()),
+            _ => {}
+        }
+    }
+    tokens
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The `n`th token from the end, or nothing.
+fn last(tokens: &[String], n: usize) -> &str {
+    match tokens.len().checked_sub(n) {
+        Some(i) => &tokens[i],
+        None => "",
+    }
+}
+
+/// Whether `text` ends inside a string, quoted identifier or Ion literal.
+fn inside_quotes(text: &str) -> bool {
+    let mut quote = None;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' || c == '`' => quote = Some(c),
+            None => {}
+        }
+    }
+    quote.is_some()
+}
+
+fn is_keyword(token: &str, keyword: &str) -> bool {
+    token.eq_ignore_ascii_case(keyword)
+}
+
+/// Whether the token after `tokens` is a table name: after FROM, INTO,
+/// UPDATE, JOIN, DROP TABLE, INDEX ON, in `history(` or in a list of tables.
+fn expects_table(tokens: &[String]) -> bool {
+    match last(tokens, 1) {
+        t if ["FROM", "INTO", "UPDATE", "JOIN"]
+            .iter()
+            .any(|k| is_keyword(t, k)) =>
+        {
+            true
+        }
+        t if is_keyword(t, "TABLE") => is_keyword(last(tokens, 2), "DROP"),
+        t if is_keyword(t, "ON") => is_keyword(last(tokens, 2), "INDEX"),
+        "(" => is_keyword(last(tokens, 2), "history"),
+        // e.g. `FROM Vehicle AS v, VehicleRegistration AS r`
+        "," => {
+            let clause = tokens.iter().rev().find(|t| {
+                ["SELECT", "FROM", "WHERE", "SET"]
+                    .iter()
+                    .any(|k| is_keyword(t, k))
+            });
+            matches!(clause, Some(t) if is_keyword(t, "FROM"))
+        }
+        _ => false,
+    }
+}
+
+/// The known tables the statement refers to.
+fn sources(tokens: &[String], catalog: &Catalog) -> Vec<Source> {
+    let mut sources = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if !expects_table(&tokens[..i]) {
+            continue;
+        }
+        if let Some(table) = catalog.table(token) {
+            let history = i > 0 && tokens[i - 1] == "(";
+            sources.push(Source {
+                table: table.to_string(),
+                revisions: history || token.starts_with(COMMITTED_PREFIX),
+            });
+        }
+    }
+    sources
+}
+
+/// Completes `word` with `candidates` (ignoring case), in the order given.
+fn matching(word: &str, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    let word = word.to_lowercase();
+    let mut matches: Vec<String> = vec![];
+    for candidate in candidates {
+        if candidate.to_lowercase().starts_with(&word) && !matches.contains(&candidate) {
+            matches.push(candidate);
+        }
+    }
+    matches
+}
+
+/// Completes the word that ends at `pos` in `line`. Returns where the
+/// completed part starts and the candidates to replace it with.
+pub(crate) fn complete(line: &str, pos: usize, catalog: &Catalog) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    if before.trim_start().starts_with('\\') {
+        return complete_command(before, catalog);
+    }
+    if inside_quotes(before) {
+        return (pos, vec![]);
+    }
+    let start = before
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !is_word_char(c) && c != '.')
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    let word = &before[start..];
+    let preceding = tokens(&before[..start]);
+    // `CREATE TABLE` is followed by a new name.
+    if is_keyword(last(&preceding, 1), "TABLE") && !expects_table(&preceding) {
+        return (pos, vec![]);
+    }
+
+    if expects_table(&preceding) {
+        let mut candidates: Vec<String> = catalog.tables.clone();
+        let last = last(&preceding, 1);
+        if is_keyword(last, "FROM") || last == "," {
+            candidates.extend(
+                catalog
+                    .tables
+                    .iter()
+                    .map(|t| format!("{}{}", COMMITTED_PREFIX, t)),
+            );
+            candidates.push("information_schema.user_tables".to_string());
+            candidates.push("history(".to_string());
+        }
+        return (start, matching(word, candidates));
+    }
+
+    let sources = sources(&tokens(line), catalog);
+    let fields = sources
+        .iter()
+        .flat_map(|s| catalog.fields.get(&s.table).into_iter().flatten())
+        .cloned();
+
+    // A path such as `v.Owner.Na`: only its last part is completed.
+    if let Some(dot) = word.rfind('.') {
+        let parent = word[..dot].rsplit('.').next().unwrap_or_default();
+        let mut candidates: Vec<String> = vec![];
+        if parent.eq_ignore_ascii_case("metadata") {
+            candidates.extend(METADATA_FIELDS.iter().map(|f| f.to_string()));
+        } else if sources.iter().any(|s| s.revisions) {
+            candidates.extend(REVISION_FIELDS.iter().map(|f| f.to_string()));
+        }
+        candidates.extend(fields);
+        return (start + dot + 1, matching(&word[dot + 1..], candidates));
+    }
+
+    if word.is_empty() {
+        return (pos, vec![]);
+    }
+    // Keywords and functions are completed in the case they are being typed
+    // in.
+    let lowercase = word.chars().all(|c| !c.is_ascii_uppercase());
+    let case = |k: &str| match lowercase {
+        true => k.to_lowercase(),
+        false => k.to_uppercase(),
+    };
+    let keywords = KEYWORDS.iter().map(|k| case(k));
+    let functions = FUNCTIONS.iter().map(|f| format!("{}(", case(f)));
+    let candidates: Vec<String> = fields.chain(keywords).chain(functions).collect();
+    (start, matching(word, candidates))
+}
+
+/// Statements that create or drop tables (or indexes), after which the
+/// catalog is out of date.
+pub(crate) fn is_ddl(statement: &str) -> bool {
+    match statement.split_whitespace().next() {
+        Some(keyword) => ["create", "drop", "undrop"]
+            .iter()
+            .any(|k| keyword.eq_ignore_ascii_case(k)),
+        None => false,
+    }
+}
+
+/// Completes a backslash command from its structopt definition: names of
+/// (sub)commands, flags and the possible values of arguments. Arguments
+/// that structopt can't know the values of (ledgers, profiles, regions and
+/// tables) are completed by their name.
+fn complete_command(before: &str, catalog: &Catalog) -> (usize, Vec<String>) {
+    let mut start = before
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0);
+    if before[start..].starts_with('\\') {
+        start += 1;
+    }
+    let word = &before[start..];
+    let args = command::split_args(before[..start].trim_start().trim_start_matches('\\'));
+
+    // Follow the arguments typed so far down to the command they are for.
+    let app = Backslash::clap();
+    let mut command = CommandDefinition::new(&app);
+    let mut positionals = 0;
+    let mut value_of = None;
+    for arg in &args {
+        if value_of.take().is_some() {
+            continue;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            value_of = command.option_by_long(long);
+        } else if let Some(short) = arg.strip_prefix('-') {
+            value_of = command.option_by_short(short);
+        } else if let Some(subcommand) = command.subcommand(arg) {
+            command = subcommand;
+            positionals = 0;
+        } else {
+            positionals += 1;
+        }
+    }
+
+    let candidates = match value_of {
+        Some(opt) => argument_values(&opt, catalog),
+        None if word.starts_with('-') => command.long_names(),
+        None => {
+            let mut candidates = command.subcommand_names();
+            if args.is_empty() {
+                // Multi-word commands such as `start transaction` aren't
+                // completed.
+                candidates.extend(
+                    runner::SIMPLE_COMMANDS
+                        .iter()
+                        .map(|(name, _)| name.to_string())
+                        .filter(|name| name.chars().all(|c| c.is_ascii_alphabetic())),
+                );
+                candidates.sort();
+            }
+            if let Some(positional) = command.positional(positionals) {
+                candidates.extend(argument_values(&positional, catalog));
+            }
+            candidates
+        }
+    };
+    (start, matching(word, candidates))
+}
+
+fn argument_values(argument: &ArgumentDefinition, catalog: &Catalog) -> Vec<String> {
+    if !argument.possible_values.is_empty() {
+        return argument
+            .possible_values
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+    }
+    match argument.name {
+        "ledger" => catalog.ledgers.clone(),
+        "profile" => aws_profiles(),
+        "region" => REGIONS.iter().map(|r| r.to_string()).collect(),
+        "table" | "source" => catalog.tables.clone(),
+        _ => vec![],
+    }
+}
+
+/// The profiles in the AWS config and credentials files. They are read on
+/// every completion, so that new profiles show up without a restart.
+fn aws_profiles() -> Vec<String> {
+    let aws = dirs::home_dir().unwrap_or_default().join(".aws");
+    let file = |var: &str, name: &str| match env::var_os(var) {
+        Some(path) => PathBuf::from(path),
+        None => aws.join(name),
+    };
+    let files = vec![
+        (file("AWS_CONFIG_FILE", "config"), true),
+        (file("AWS_SHARED_CREDENTIALS_FILE", "credentials"), false),
+    ];
+
+    let mut profiles: Vec<String> = vec![];
+    for (path, config) in files {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        for profile in profile_names(&text, config) {
+            if !profiles.contains(&profile) {
+                profiles.push(profile);
+            }
+        }
+    }
+    profiles
+}
+
+/// The profiles in an AWS config file (`[profile NAME]` or `[default]`) or
+/// credentials file (`[NAME]`).
+fn profile_names(text: &str, config: bool) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let section = line.trim().strip_prefix('[')?.strip_suffix(']')?.trim();
+            match config && section != "default" {
+                true => Some(section.strip_prefix("profile ")?.trim().to_string()),
+                false => Some(section.to_string()),
+            }
+        })
+        .collect()
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Reloads the table (and configured ledger) names used for completion. Completion is a
+    /// convenience, so failures are only logged.
+    pub(crate) async fn refresh_catalog(&mut self) {
+        self.catalog.ledgers = match self.deps.env.config().ledgers {
+            Some(ref ledgers) => ledgers.iter().map(|l| l.name.clone()).collect(),
+            None => vec![],
+        };
+        let tables = self
+            .query("SELECT VALUE name FROM information_schema.user_tables WHERE status = 'ACTIVE'")
+            .await;
+        match tables {
+            Ok(tables) => {
+                let mut tables: Vec<String> = tables
+                    .iter()
+                    .filter_map(|t| t.as_str().map(|t| t.to_string()))
+                    .collect();
+                tables.sort();
+                self.catalog.fields.retain(|t, _| tables.contains(t));
+                self.catalog.tables = tables;
+            }
+            Err(e) => self
+                .deps
+                .ui
+                .debug(&format!("unable to load table names for completion: {}", e)),
+        }
+        self.deps.ui.set_catalog(self.catalog.clone());
+    }
+
+    /// Learns field names for completion from the results of `statement`.
+    /// Only the documents fields are learned from are parsed.
+    ///
+    /// Tables the statement refers to that nothing was learned about yet
+    /// (say, because it is an insert or a join) are sampled once, reading
+    /// no more than `LEARNED_DOCUMENTS` documents.
+    pub(crate) async fn learn_fields(&mut self, statement: &str, results: &StatementResults) {
+        if let Ok(docs) = results::parse_values(results.raw_values().take(LEARNED_DOCUMENTS)) {
+            self.catalog.learn_fields(statement, &docs);
+        }
+        // Scripts don't need completion, so they don't pay for the samples.
+        if atty::is(atty::Stream::Stdin) {
+            for table in self.catalog.unsampled_tables(statement) {
+                match self.sample_documents(&table, LEARNED_DOCUMENTS).await {
+                    Ok(docs) => self.catalog.learn_table_fields(&table, &docs),
+                    Err(e) => self
+                        .deps
+                        .ui
+                        .debug(&format!("unable to sample {} for completion: {}", table, e)),
+                }
+            }
+        }
+        self.deps.ui.set_catalog(self.catalog.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ion_rs::value::reader::{element_reader, ElementReader};
+
+    fn catalog() -> Catalog {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "Vehicle".to_string(),
+            vec!["VIN".to_string(), "Make".to_string()],
+        );
+        Catalog {
+            tables: vec!["Person".to_string(), "Vehicle".to_string()],
+            fields,
+            ledgers: vec!["vehicle-registration".to_string()],
+        }
+    }
+
+    fn completions(line: &str) -> Vec<String> {
+        let (start, candidates) = complete(line, line.len(), &catalog());
+        candidates
+            .iter()
+            .map(|c| format!("{}{}", &line[..start], c))
+            .collect()
+    }
+
+    #[test]
+    fn completes_tables() {
+        assert_eq!(
+            vec!["SELECT * FROM Vehicle"],
+            completions("SELECT * FROM V")
+        );
+        assert_eq!(
+            vec![
+                "select * from _ql_committed_Person",
+                "select * from _ql_committed_Vehicle"
+            ],
+            completions("select * from _ql")
+        );
+        assert_eq!(vec!["INSERT INTO Person"], completions("INSERT INTO P"));
+        assert_eq!(vec!["UPDATE Vehicle"], completions("UPDATE Ve"));
+        assert_eq!(
+            vec!["SELECT * FROM history(Vehicle"],
+            completions("SELECT * FROM history(V")
+        );
+        assert_eq!(
+            vec!["SELECT * FROM Person AS p, Vehicle"],
+            completions("SELECT * FROM Person AS p, V")
+        );
+        assert_eq!(
+            vec!["SELECT * FROM history("],
+            completions("SELECT * FROM hist")
+        );
+
+        // Tables are only completed where a table is expected.
+        assert_eq!(Vec::<String>::new(), completions("SELECT Pe"));
+        assert_eq!(
+            Vec::<String>::new(),
+            completions("INSERT INTO Person VALUE {'a': 'FROM V")
+        );
+        assert_eq!(Vec::<String>::new(), completions("CREATE TABLE V"));
+    }
+
+    #[test]
+    fn completes_keywords_and_fields() {
+        assert_eq!(vec!["SELECT"], completions("SEL"));
+        assert_eq!(vec!["select"], completions("sel"));
+        assert_eq!(
+            vec![
+                "SELECT * FROM Vehicle WHERE Make",
+                "SELECT * FROM Vehicle WHERE MISSING",
+                "SELECT * FROM Vehicle WHERE MAX(",
+                "SELECT * FROM Vehicle WHERE MIN(",
+            ],
+            completions("SELECT * FROM Vehicle WHERE M")
+        );
+        assert_eq!(
+            vec!["SELECT v.VIN FROM Vehicle AS v"],
+            complete_at("SELECT v.V FROM Vehicle AS v", 10)
+        );
+        assert_eq!(
+            vec!["SELECT h.metadata.txId FROM history(Vehicle) AS h"],
+            complete_at("SELECT h.metadata.txI FROM history(Vehicle) AS h", 21)
+        );
+        assert_eq!(
+            vec!["SELECT c.data FROM _ql_committed_Vehicle AS c"],
+            complete_at("SELECT c.d FROM _ql_committed_Vehicle AS c", 10)
+        );
+    }
+
+    #[test]
+    fn completes_commands() {
+        assert_eq!(vec!["\\set", "\\show", "\\status"], completions("\\s"));
+        assert_eq!(vec!["\\set edit-mode"], completions("\\set edit-m"));
+        assert_eq!(
+            vec!["\\set edit-mode emacs", "\\set edit-mode vi"],
+            completions("\\set edit-mode ")
+        );
+        assert_eq!(vec!["\\set format table"], completions("\\set format t"));
+        assert_eq!(vec!["\\dryrun off"], completions("\\dryrun of"));
+        assert_eq!(vec!["\\use --region"], completions("\\use --r"));
+        assert_eq!(
+            vec!["\\use -l vehicle-registration"],
+            completions("\\use -l v")
+        );
+        assert_eq!(
+            vec!["\\use -p x --region us-west-2"],
+            completions("\\use -p x --region us-w")
+        );
+        assert_eq!(
+            vec!["\\ledger create x --permissions-mode ALLOW_ALL"],
+            completions("\\ledger create x --permissions-mode a")
+        );
+        assert_eq!(vec!["\\describe Vehicle"], completions("\\describe V"));
+        assert_eq!(
+            vec!["\\import a.csv into Person"],
+            completions("\\import a.csv into P")
+        );
+        assert_eq!(
+            vec![
+                "\\export Vehicle a.ion --format jsonl",
+                "\\export Vehicle a.ion --format json"
+            ],
+            completions("\\export Vehicle a.ion --format j")
+        );
+        assert_eq!(
+            vec!["\\describe\u{a0}Vehicle"],
+            completions("\\describe\u{a0}V")
+        );
+    }
+
+    #[test]
+    fn completes_after_non_ascii() {
+        assert_eq!(
+            vec!["SELECT * FROM Vehicle WHERE éVIN"],
+            completions("SELECT * FROM Vehicle WHERE éVI")
+        );
+        assert_eq!(Vec::<String>::new(), completions("SELECT é"));
+    }
+
+    #[test]
+    fn reads_profiles() {
+        let config = "[default]\nregion = us-east-1\n\n[profile dev]\n[sso-session s]\n";
+        assert_eq!(vec!["default", "dev"], profile_names(config, true));
+        assert_eq!(
+            vec!["default", "prod"],
+            profile_names("[default]\n; comment\n[prod]\n", false)
+        );
+    }
+
+    fn complete_at(line: &str, pos: usize) -> Vec<String> {
+        let (start, candidates) = complete(line, pos, &catalog());
+        candidates
+            .iter()
+            .map(|c| format!("{}{}{}", &line[..start], c, &line[pos..]))
+            .collect()
+    }
+
+    #[test]
+    fn learns_fields() {
+        let mut catalog = catalog();
+        let docs = element_reader()
+            .read_all(br#"{ data: { Name: "a", Age: 3 }, metadata: { id: "x" } }"#)
+            .unwrap();
+        assert_eq!(
+            vec!["Person"],
+            catalog.unsampled_tables("SELECT * FROM history(Person) AS h")
+        );
+        catalog.learn_fields("SELECT * FROM history(Person) AS h", &docs);
+        // Fields are learned in the order the struct has them in, which is
+        // not the order they were written in.
+        let mut fields = catalog.fields["Person"].clone();
+        fields.sort();
+        assert_eq!(vec!["Age", "Name"], fields);
+        assert!(catalog
+            .unsampled_tables("SELECT * FROM Vehicle, Person")
+            .is_empty());
+
+        let docs = element_reader()
+            .read_all(br#"{ VIN: "1", Year: 2020 }"#)
+            .unwrap();
+        catalog.learn_fields("SELECT * FROM Vehicle", &docs);
+        assert_eq!(
+            Some(&vec![
+                "VIN".to_string(),
+                "Make".to_string(),
+                "Year".to_string()
+            ]),
+            catalog.fields.get("Vehicle")
+        );
+
+        // Joins don't say which table a field is from.
+        catalog.learn_fields("SELECT * FROM Vehicle, Person", &docs);
+        assert_eq!(3, catalog.fields["Vehicle"].len());
+
+        // Neither do projections, aggregates or DML, whose tables are
+        // sampled instead.
+        let mut catalog = self::catalog();
+        let inserted = element_reader()
+            .read_all(br#"{ documentId: "x" }"#)
+            .unwrap();
+        catalog.learn_fields("INSERT INTO Person VALUE { 'Name': 'a' }", &inserted);
+        let counted = element_reader().read_all(br#"{ _1: 3 }"#).unwrap();
+        catalog.learn_fields("SELECT COUNT(*) FROM Person", &counted);
+        catalog.learn_fields("SELECT VIN AS v FROM Vehicle", &docs);
+        assert_eq!(None, catalog.fields.get("Person"));
+        assert_eq!(2, catalog.fields["Vehicle"].len());
+        assert_eq!(
+            vec!["Person"],
+            catalog.unsampled_tables("INSERT INTO Person VALUE { 'Name': 'a' }")
+        );
+
+        assert!(is_ddl("create table Foo"));
+        assert!(!is_ddl("SELECT * FROM Foo"));
+    }
+}
@@ -0,0 +1,731 @@
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use amazon_qldb_driver::QldbSession;
+use anyhow::{anyhow, Result};
+use aws_sdk_qldb::model::{PermissionsMode, ValueHolder};
+use aws_sdk_qldb::{config, Client, Endpoint, Region};
+use aws_smithy_types::date_time::Format;
+use comfy_table::Table;
+use http::Uri;
+use tokio::{signal, time};
+
+use crate::command::LedgerCommand;
+use crate::qldb_hash::QldbHash;
+use crate::runner::Runner;
+use crate::{awssdk_driver, error, settings::Environment};
+
+/// How often `\ledger create --wait` checks whether the ledger is active.
+const CREATE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long `\ledger create --wait` waits before giving up. Ledgers usually
+/// become active within a few minutes.
+const CREATE_WAIT_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// A client for the QLDB control plane (the `qldb` API, as opposed to the
+/// `qldb-session` API used to run statements). It is used to find and manage
+/// ledgers.
+pub(crate) struct ControlPlane {
+    client: Client,
+}
+
+/// What the shell shows about a ledger. Everything but the name is optional,
+/// as it is whatever QLDB returned.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LedgerSummary {
+    pub(crate) name: String,
+    pub(crate) arn: Option<String>,
+    pub(crate) state: Option<String>,
+    pub(crate) created: Option<String>,
+    pub(crate) permissions_mode: Option<String>,
+    pub(crate) deletion_protection: Option<bool>,
+}
+
+/// A digest of a ledger's journal, as returned by `GetDigest`.
+pub(crate) struct LedgerDigest {
+    pub(crate) digest: QldbHash,
+    /// The address of the last block the digest covers, as Ion text.
+    pub(crate) tip_address: String,
+}
+
+/// A proof that a revision is in a digest, as returned by `GetRevision`.
+/// Both are Ion text.
+pub(crate) struct RevisionProof {
+    /// A list of the hashes to combine with the revision hash.
+    pub(crate) proof: String,
+    /// The revision itself, including its hash.
+    pub(crate) revision: String,
+}
+
+fn ion_text(value: Option<&ValueHolder>, what: &str) -> Result<String> {
+    match value.and_then(|value| value.ion_text()) {
+        Some(text) => Ok(text.to_string()),
+        None => Err(anyhow!("QLDB did not return {}", what)),
+    }
+}
+
+impl ControlPlane {
+    /// Builds a client with the same credentials (`profile`) and region the
+    /// session client would use. `endpoint` overrides the control plane
+    /// endpoint, e.g. to point at a local stand-in.
+    pub(crate) async fn new(
+        profile: Option<&str>,
+        region: Region,
+        endpoint: Option<&str>,
+    ) -> Result<ControlPlane> {
+        let aws_config = awssdk_driver::load_aws_config(profile).await;
+        let conf = config::Builder::from(&aws_config).region(region);
+        let conf = match endpoint {
+            Some(endpoint) => {
+                // See `build_client` for why the slash is stripped.
+                let clean = endpoint.trim_matches(|c| c == '/');
+                conf.endpoint_resolver(Endpoint::immutable(Uri::from_str(clean)?))
+            }
+            None => conf,
+        };
+
+        Ok(ControlPlane {
+            client: Client::from_conf(conf.build()),
+        })
+    }
+
+    /// Builds a client for the region and profile of the current ledger.
+    pub(crate) async fn from_env(env: &Environment) -> Result<ControlPlane> {
+        let (profile, endpoint) = {
+            let current_ledger = env.current_ledger();
+            (
+                current_ledger.profile.clone(),
+                current_ledger.qldb_endpoint.clone(),
+            )
+        };
+        ControlPlane::new(
+            profile.as_deref(),
+            env.current_region(),
+            endpoint.as_deref(),
+        )
+        .await
+    }
+
+    /// Lists every ledger in the region (following pagination), along with
+    /// the details only `DescribeLedger` returns.
+    pub(crate) async fn list_ledgers(&self) -> Result<Vec<LedgerSummary>> {
+        let mut names = vec![];
+        let mut next_token = None;
+        loop {
+            let page = self
+                .client
+                .list_ledgers()
+                .set_next_token(next_token)
+                .send()
+                .await?;
+            for ledger in page.ledgers().unwrap_or_default() {
+                if let Some(name) = ledger.name() {
+                    names.push(name.to_string());
+                }
+            }
+            next_token = page.next_token().map(|token| token.to_string());
+            if next_token.is_none() {
+                break;
+            }
+        }
+
+        let mut ledgers = Vec::with_capacity(names.len());
+        for name in names {
+            ledgers.push(self.describe_ledger(&name).await?);
+        }
+        Ok(ledgers)
+    }
+
+    pub(crate) async fn describe_ledger(&self, name: &str) -> Result<LedgerSummary> {
+        let described = self.client.describe_ledger().name(name).send().await?;
+        Ok(LedgerSummary {
+            name: described.name().unwrap_or(name).to_string(),
+            arn: described.arn().map(|arn| arn.to_string()),
+            state: described.state().map(|state| state.as_str().to_string()),
+            created: described
+                .creation_date_time()
+                .and_then(|created| created.fmt(Format::DateTime).ok()),
+            permissions_mode: described
+                .permissions_mode()
+                .map(|mode| mode.as_str().to_string()),
+            deletion_protection: described.deletion_protection(),
+        })
+    }
+
+    pub(crate) async fn create_ledger(
+        &self,
+        name: &str,
+        permissions_mode: &str,
+        deletion_protection: bool,
+    ) -> Result<LedgerSummary> {
+        self.client
+            .create_ledger()
+            .name(name)
+            .permissions_mode(PermissionsMode::from(&permissions_mode.to_uppercase()[..]))
+            .deletion_protection(deletion_protection)
+            .send()
+            .await?;
+        self.describe_ledger(name).await
+    }
+
+    pub(crate) async fn update_ledger(
+        &self,
+        name: &str,
+        permissions_mode: Option<&str>,
+        deletion_protection: Option<bool>,
+    ) -> Result<()> {
+        if let Some(deletion_protection) = deletion_protection {
+            self.client
+                .update_ledger()
+                .name(name)
+                .deletion_protection(deletion_protection)
+                .send()
+                .await?;
+        }
+        if let Some(permissions_mode) = permissions_mode {
+            self.client
+                .update_ledger_permissions_mode()
+                .name(name)
+                .permissions_mode(PermissionsMode::from(&permissions_mode.to_uppercase()[..]))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn get_digest(&self, name: &str) -> Result<LedgerDigest> {
+        let digest = self.client.get_digest().name(name).send().await?;
+        Ok(LedgerDigest {
+            digest: match digest.digest() {
+                Some(blob) => QldbHash::from_bytes(blob.as_ref().to_vec()),
+                None => Err(anyhow!("QLDB did not return a digest"))?,
+            },
+            tip_address: ion_text(digest.digest_tip_address(), "a digest tip address")?,
+        })
+    }
+
+    /// Gets the revision of `document_id` at `block_address` along with a
+    /// proof that it is in the digest ending at `tip_address`. Addresses are
+    /// Ion text, e.g. `{strandId: "...", sequenceNo: 42}`.
+    pub(crate) async fn get_revision(
+        &self,
+        name: &str,
+        block_address: &str,
+        document_id: &str,
+        tip_address: &str,
+    ) -> Result<RevisionProof> {
+        let revision = self
+            .client
+            .get_revision()
+            .name(name)
+            .block_address(ValueHolder::builder().ion_text(block_address).build())
+            .document_id(document_id)
+            .digest_tip_address(ValueHolder::builder().ion_text(tip_address).build())
+            .send()
+            .await?;
+        Ok(RevisionProof {
+            proof: ion_text(revision.proof(), "a proof")?,
+            revision: ion_text(revision.revision(), "the revision")?,
+        })
+    }
+
+    pub(crate) async fn delete_ledger(&self, name: &str) -> Result<()> {
+        self.client.delete_ledger().name(name).send().await?;
+        Ok(())
+    }
+
+    /// The tags of the resource `arn`, sorted by key.
+    pub(crate) async fn tags(&self, arn: &str) -> Result<Vec<(String, String)>> {
+        let listed = self
+            .client
+            .list_tags_for_resource()
+            .resource_arn(arn)
+            .send()
+            .await?;
+        let mut tags: Vec<_> = match listed.tags() {
+            Some(tags) => tags
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone().unwrap_or_default()))
+                .collect(),
+            None => vec![],
+        };
+        tags.sort();
+        Ok(tags)
+    }
+
+    pub(crate) async fn tag(&self, arn: &str, tags: &[(String, String)]) -> Result<()> {
+        if tags.is_empty() {
+            return Ok(());
+        }
+        let mut request = self.client.tag_resource().resource_arn(arn);
+        for (key, value) in tags {
+            request = request.tags(key, Some(value.clone()));
+        }
+        request.send().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn untag(&self, arn: &str, keys: &[String]) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut request = self.client.untag_resource().resource_arn(arn);
+        for key in keys {
+            request = request.tag_keys(key);
+        }
+        request.send().await?;
+        Ok(())
+    }
+}
+
+/// Asks the user to pick one of `ledgers`, by number or by name. Used when
+/// the shell is started without a ledger.
+pub(crate) fn pick_ledger<R, W>(
+    ledgers: &[LedgerSummary],
+    input: R,
+    mut output: W,
+) -> Result<String>
+where
+    R: BufRead,
+    W: Write,
+{
+    if ledgers.is_empty() {
+        Err(error::usage_error(
+            "`--ledger` was not specified, there is no `default_ledger` in your config \
+            and there are no ledgers in this region",
+        ))?
+    }
+
+    writeln!(output, "No ledger was specified. Ledgers in this region:")?;
+    for (i, ledger) in ledgers.iter().enumerate() {
+        writeln!(
+            output,
+            "  {}. {} ({})",
+            i + 1,
+            ledger.name,
+            ledger.state.as_deref().unwrap_or("UNKNOWN")
+        )?;
+    }
+
+    let mut lines = input.lines();
+    loop {
+        write!(output, "Pick a ledger [1-{}]: ", ledgers.len())?;
+        output.flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => Err(error::usage_error("no ledger was picked"))?,
+        };
+        let choice = line.trim();
+        let picked = match choice.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= ledgers.len() => Some(&ledgers[n - 1]),
+            _ => ledgers.iter().find(|ledger| ledger.name == choice),
+        };
+        match picked {
+            Some(ledger) => return Ok(ledger.name.clone()),
+            None => writeln!(output, "'{}' is not one of the ledgers above", choice)?,
+        }
+    }
+}
+
+/// Asks the user to type the name of the ledger they are deleting again.
+pub(crate) fn confirm_deletion<R, W>(name: &str, mut input: R, mut output: W) -> Result<bool>
+where
+    R: BufRead,
+    W: Write,
+{
+    write!(
+        output,
+        "Ledger {} and all of its data will be deleted. Type its name to confirm: ",
+        name
+    )?;
+    output.flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(line.trim() == name)
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Commands that change a ledger only say what they would do in dry run
+    /// mode, like transactions are rolled back.
+    fn is_dry_run(&self, action: &str) -> bool {
+        let dry_run = self.deps.env.config().ui.dry_run;
+        if dry_run {
+            self.deps
+                .ui
+                .println(&format!("DRY RUN — would have {}", action));
+        }
+        dry_run
+    }
+
+    /// Waits (with `--wait`) until a new ledger is active, for at most
+    /// [`CREATE_WAIT_TIMEOUT`]. Ctrl-C stops waiting, not the creation.
+    async fn wait_until_active(
+        &self,
+        control_plane: &ControlPlane,
+        mut ledger: LedgerSummary,
+    ) -> Result<LedgerSummary> {
+        let started = Instant::now();
+        while ledger.state.as_deref() != Some("ACTIVE") {
+            if started.elapsed() >= CREATE_WAIT_TIMEOUT {
+                self.deps.ui.println(&format!(
+                    "gave up waiting for ledger {} after {}s",
+                    ledger.name,
+                    CREATE_WAIT_TIMEOUT.as_secs()
+                ));
+                break;
+            }
+            self.deps.ui.println(&format!(
+                "waiting for ledger {} to become active ({}), Ctrl-C to stop waiting",
+                ledger.name,
+                ledger.state.as_deref().unwrap_or("UNKNOWN")
+            ));
+            tokio::select! {
+                _ = time::sleep(CREATE_POLL_INTERVAL) => {}
+                _ = signal::ctrl_c() => break,
+            }
+            ledger = control_plane.describe_ledger(&ledger.name).await?;
+        }
+        Ok(ledger)
+    }
+
+    pub(crate) async fn handle_show_ledgers(&self) -> Result<()> {
+        let ledgers = ControlPlane::from_env(&self.deps.env)
+            .await?
+            .list_ledgers()
+            .await?;
+
+        let current = self.deps.env.current_ledger().name.clone();
+        let configured: Vec<String> = match self.deps.env.config().ledgers {
+            Some(ref ledgers) => ledgers.iter().map(|l| l.name.clone()).collect(),
+            None => vec![],
+        };
+
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        table.set_header(vec![
+            "",
+            "name",
+            "state",
+            "created",
+            "permissions-mode",
+            "deletion-protection",
+            "configured",
+        ]);
+        for ledger in ledgers {
+            table.add_row(vec![
+                if ledger.name == current { "*" } else { "" }.to_string(),
+                ledger.name.clone(),
+                ledger.state.unwrap_or_default(),
+                ledger.created.unwrap_or_default(),
+                ledger.permissions_mode.unwrap_or_default(),
+                ledger
+                    .deletion_protection
+                    .map(|on| on.to_string())
+                    .unwrap_or_default(),
+                if configured.contains(&ledger.name) {
+                    "yes"
+                } else {
+                    ""
+                }
+                .to_string(),
+            ]);
+        }
+        self.deps.ui.println(&format!("{}", table));
+        Ok(())
+    }
+
+    pub(crate) async fn handle_ledger_command(&self, command: LedgerCommand) -> Result<()> {
+        let control_plane = ControlPlane::from_env(&self.deps.env).await?;
+        let current = self.deps.env.current_ledger().name.clone();
+
+        match command {
+            LedgerCommand::Create {
+                name,
+                permissions_mode,
+                deletion_protection,
+                tags,
+                wait,
+            } => {
+                if self.is_dry_run(&format!("created ledger {}", name)) {
+                    return Ok(());
+                }
+                let mut ledger = control_plane
+                    .create_ledger(&name, &permissions_mode, deletion_protection)
+                    .await?;
+                if let Some(ref arn) = ledger.arn {
+                    control_plane.tag(arn, &tags).await?;
+                }
+                if wait {
+                    ledger = self.wait_until_active(&control_plane, ledger).await?;
+                }
+                self.deps.ui.println(&format!(
+                    "ledger {} is {}, switch to it with '\\use -l {}'",
+                    name,
+                    ledger.state.as_deref().unwrap_or("UNKNOWN"),
+                    name
+                ));
+            }
+            LedgerCommand::Describe { name } => {
+                let ledger = control_plane
+                    .describe_ledger(name.as_deref().unwrap_or(&current))
+                    .await?;
+                self.print_ledger(&ledger);
+            }
+            LedgerCommand::Update {
+                name,
+                permissions_mode,
+                deletion_protection,
+            } => {
+                if permissions_mode.is_none() && deletion_protection.is_none() {
+                    Err(error::usage_error(
+                        "nothing to update, give --permissions-mode or --deletion-protection",
+                    ))?
+                }
+                let name = name.as_deref().unwrap_or(&current);
+                if self.is_dry_run(&format!("updated ledger {}", name)) {
+                    return Ok(());
+                }
+                control_plane
+                    .update_ledger(name, permissions_mode.as_deref(), deletion_protection)
+                    .await?;
+                self.print_ledger(&control_plane.describe_ledger(name).await?);
+            }
+            LedgerCommand::Delete { name, yes } => {
+                let ledger = control_plane.describe_ledger(&name).await?;
+                if ledger.deletion_protection == Some(true) {
+                    Err(error::usage_error(format!(
+                        "ledger {} has deletion protection, turn it off first with \
+                        '\\ledger update {} --deletion-protection false'",
+                        name, name
+                    )))?
+                }
+                if self.is_dry_run(&format!("deleted ledger {}", name)) {
+                    return Ok(());
+                }
+                if !yes {
+                    if !atty::is(atty::Stream::Stdin) {
+                        Err(error::usage_error(
+                            "give --yes to delete a ledger without being asked to confirm",
+                        ))?
+                    }
+                    if !confirm_deletion(&name, io::stdin().lock(), io::stdout())? {
+                        Err(error::usage_error(format!(
+                            "the name didn't match, ledger {} was not deleted",
+                            name
+                        )))?
+                    }
+                }
+                control_plane.delete_ledger(&name).await?;
+                self.deps
+                    .ui
+                    .println(&format!("ledger {} is being deleted", name));
+                if name == current {
+                    self.deps.ui.println(
+                        "this is the current ledger, switch to another one with '\\use -l LEDGER'",
+                    );
+                }
+            }
+            LedgerCommand::Tags { name, add, remove } => {
+                let ledger = control_plane
+                    .describe_ledger(name.as_deref().unwrap_or(&current))
+                    .await?;
+                let arn = match ledger.arn {
+                    Some(ref arn) => arn,
+                    None => Err(error::bug("DescribeLedger did not return an arn"))?,
+                };
+                let changes = !add.is_empty() || !remove.is_empty();
+                if changes
+                    && self.is_dry_run(&format!("changed the tags of ledger {}", ledger.name))
+                {
+                    return Ok(());
+                }
+                control_plane.tag(arn, &add).await?;
+                control_plane.untag(arn, &remove).await?;
+                for (key, value) in control_plane.tags(arn).await? {
+                    self.deps.ui.println(&format!("{}={}", key, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print_ledger(&self, ledger: &LedgerSummary) {
+        let lines = vec![
+            ("name", Some(ledger.name.clone())),
+            ("arn", ledger.arn.clone()),
+            ("state", ledger.state.clone()),
+            ("created", ledger.created.clone()),
+            ("permissions-mode", ledger.permissions_mode.clone()),
+            (
+                "deletion-protection",
+                ledger.deletion_protection.map(|on| on.to_string()),
+            ),
+        ];
+        for (name, value) in lines {
+            self.deps
+                .ui
+                .println(&format!("{}: {}", name, value.unwrap_or_default()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_smithy_http::body::SdkBody;
+    use aws_smithy_http::result::ConnectorError;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+
+    /// Stands in for the QLDB control plane: answers each request with the
+    /// body of the first response whose key is part of the request's path and
+    /// query, and records the requests.
+    #[derive(Clone)]
+    struct StandIn {
+        responses: Arc<Vec<(&'static str, &'static str)>>,
+        requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl tower::Service<http::Request<SdkBody>> for StandIn {
+        type Response = http::Response<SdkBody>;
+        type Error = ConnectorError;
+        type Future = futures::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<SdkBody>) -> Self::Future {
+            let uri = request
+                .uri()
+                .path_and_query()
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            self.requests.lock().unwrap().push(uri.clone());
+            let response = match self.responses.iter().find(|(key, _)| uri.contains(key)) {
+                Some((_, body)) => http::Response::builder()
+                    .status(200)
+                    .body(SdkBody::from(*body)),
+                None => http::Response::builder()
+                    .status(404)
+                    .body(SdkBody::from("{}")),
+            };
+            futures::future::ready(Ok(response.unwrap()))
+        }
+    }
+
+    fn control_plane(stand_in: StandIn) -> ControlPlane {
+        let conf = config::Config::builder()
+            .region(Region::new("us-east-1"))
+            .credentials_provider(aws_types::Credentials::new(
+                "AKID", "SECRET", None, None, "test",
+            ))
+            .build();
+        ControlPlane {
+            client: Client::from_conf_conn(conf, stand_in),
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_every_page_of_ledgers() -> Result<()> {
+        let stand_in = StandIn {
+            responses: Arc::new(vec![
+                ("next_token=page2", r#"{"Ledgers": [{"Name": "two"}]}"#),
+                (
+                    "/ledgers/one",
+                    r#"{"Name": "one", "Arn": "arn:one", "State": "ACTIVE",
+                        "PermissionsMode": "STANDARD", "DeletionProtection": true}"#,
+                ),
+                ("/ledgers/two", r#"{"Name": "two", "State": "CREATING"}"#),
+                (
+                    "/ledgers",
+                    r#"{"Ledgers": [{"Name": "one"}], "NextToken": "page2"}"#,
+                ),
+            ]),
+            requests: Default::default(),
+        };
+        let ledgers = control_plane(stand_in.clone()).list_ledgers().await?;
+
+        assert_eq!(
+            vec![
+                LedgerSummary {
+                    name: "one".to_string(),
+                    arn: Some("arn:one".to_string()),
+                    state: Some("ACTIVE".to_string()),
+                    created: None,
+                    permissions_mode: Some("STANDARD".to_string()),
+                    deletion_protection: Some(true),
+                },
+                LedgerSummary {
+                    name: "two".to_string(),
+                    state: Some("CREATING".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ledgers
+        );
+        assert_eq!(4, stand_in.requests.lock().unwrap().len());
+
+        let mut output = vec![];
+        assert_eq!("two", pick_ledger(&ledgers, &b"2\n"[..], &mut output)?);
+        assert!(String::from_utf8(output)?.contains("  2. two (CREATING)\n"));
+        Ok(())
+    }
+
+    fn ledgers() -> Vec<LedgerSummary> {
+        vec![
+            LedgerSummary {
+                name: "one".to_string(),
+                state: Some("ACTIVE".to_string()),
+                ..Default::default()
+            },
+            LedgerSummary {
+                name: "two".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn pick_by_number_or_name() -> Result<()> {
+        let mut output = vec![];
+        assert_eq!("two", pick_ledger(&ledgers(), &b"2\n"[..], &mut output)?);
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("  1. one (ACTIVE)\n  2. two (UNKNOWN)\n"));
+
+        assert_eq!("one", pick_ledger(&ledgers(), &b"one\n"[..], vec![])?);
+        Ok(())
+    }
+
+    #[test]
+    fn deletion_needs_the_name() -> Result<()> {
+        let mut output = vec![];
+        assert!(confirm_deletion("prod", &b"prod\n"[..], &mut output)?);
+        assert!(String::from_utf8(output)?.ends_with("Type its name to confirm: "));
+
+        assert!(!confirm_deletion("prod", &b"y\n"[..], vec![])?);
+        assert!(!confirm_deletion("prod", &b""[..], vec![])?);
+        Ok(())
+    }
+
+    #[test]
+    fn pick_retries_invalid_choices() -> Result<()> {
+        let mut output = vec![];
+        assert_eq!(
+            "one",
+            pick_ledger(&ledgers(), &b"0\nthree\n1\n"[..], &mut output)?
+        );
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("'0' is not one of the ledgers above"));
+        assert!(output.contains("'three' is not one of the ledgers above"));
+
+        assert!(pick_ledger(&ledgers(), &b""[..], vec![]).is_err());
+        assert!(pick_ledger(&[], &b"1\n"[..], vec![]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use amazon_qldb_driver::{QldbSession, StatementResults};
+use anyhow::Result;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::{Element, Sequence, Struct, SymbolToken};
+use ion_rs::IonType;
+
+use crate::command::DiffCommand;
+use crate::history;
+use crate::runner::Runner;
+use crate::schema::{quote_identifier, quote_string};
+use crate::{error, results};
+
+/// Values longer than this are cut short when displayed in a diff.
+const MAX_VALUE_WIDTH: usize = 60;
+
+/// Results larger than this (as binary Ion) are not kept for `\diff results`
+/// and `\hash`, so that a large query doesn't hold on to its results.
+const MAX_RECENT_RESULTS_BYTES: usize = 16 * 1024 * 1024;
+
+/// The documents a statement returned, kept as binary Ion and only parsed
+/// when they are needed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RecentResults {
+    Kept(Vec<Vec<u8>>),
+    /// Results that were too large to keep, and their size.
+    TooLarge(usize),
+}
+
+impl RecentResults {
+    fn keep<I>(values: I) -> RecentResults
+    where
+        I: Iterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut kept = vec![];
+        let mut size = 0;
+        for value in values {
+            size += value.as_ref().len();
+            if size <= MAX_RECENT_RESULTS_BYTES {
+                kept.push(value.as_ref().to_vec());
+            }
+        }
+        match size <= MAX_RECENT_RESULTS_BYTES {
+            true => RecentResults::Kept(kept),
+            false => RecentResults::TooLarge(size),
+        }
+    }
+
+    pub(crate) fn elements(&self) -> Result<Vec<OwnedElement>> {
+        match self {
+            RecentResults::Kept(values) => results::parse_values(values.iter()),
+            RecentResults::TooLarge(size) => Err(error::usage_error(format!(
+                "the results were too large to keep ({} bytes, at most {} are kept)",
+                size, MAX_RECENT_RESULTS_BYTES
+            )))?,
+        }
+    }
+}
+
+/// A difference between two Ion values, at `path` (e.g. `Owner.Names[0]`).
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Change {
+    Added {
+        path: String,
+        value: String,
+    },
+    Removed {
+        path: String,
+        value: String,
+    },
+    Changed {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {}: {}", display_path(path), value),
+            Change::Removed { path, value } => write!(f, "- {}: {}", display_path(path), value),
+            Change::Changed { path, old, new } => {
+                write!(f, "~ {}: {} -> {}", display_path(path), old, new)
+            }
+        }
+    }
+}
+
+impl Change {
+    /// Like `to_string`, but with ANSI colors: green for added, red for
+    /// removed and yellow for changed paths.
+    pub(crate) fn colored(&self) -> String {
+        let code = match self {
+            Change::Added { .. } => 32,
+            Change::Removed { .. } => 31,
+            Change::Changed { .. } => 33,
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, self)
+    }
+}
+
+/// Whether changes should be printed with colors, see `Change::colored`.
+pub(crate) fn use_color() -> bool {
+    atty::is(atty::Stream::Stdout)
+}
+
+fn display_path(path: &str) -> &str {
+    match path {
+        "" => "(value)",
+        path => path,
+    }
+}
+
+/// Compares `old` and `new` structurally. Structs are compared field by
+/// field and lists item by item, so that only the paths that actually
+/// differ are reported.
+///
+/// Values are compared the way Ion does, rather than by what they mean:
+/// `1.0` and `1.00` differ (in precision), as do `2022T` and `2022-01-01T`,
+/// and values that only differ in their annotations.
+pub(crate) fn diff(old: Option<&OwnedElement>, new: Option<&OwnedElement>) -> Vec<Change> {
+    let mut changes = vec![];
+    diff_at("", old, new, &mut changes);
+    changes
+}
+
+/// Compares two sets of documents (e.g. query results). QLDB returns
+/// results in no particular order, so if every document has an id (see
+/// [`by_id`]) documents are matched on it, and the paths start with the id,
+/// e.g. `[8F0TPCmdNQ6JTRpiLj2TmW].Name`. Otherwise they are compared item by
+/// item and the paths start with the position of the document, e.g.
+/// `[0].Name`.
+pub(crate) fn diff_all(old: &[OwnedElement], new: &[OwnedElement]) -> Vec<Change> {
+    let mut changes = vec![];
+    match (by_id(old), by_id(new)) {
+        (Some(old), Some(new)) => {
+            let ids: BTreeSet<&str> = old.keys().chain(new.keys()).copied().collect();
+            for id in ids {
+                let (old, new) = (old.get(id).copied(), new.get(id).copied());
+                diff_at(&format!("[{}]", id), old, new, &mut changes);
+            }
+        }
+        _ => {
+            for i in 0..old.len().max(new.len()) {
+                diff_at(&format!("[{}]", i), old.get(i), new.get(i), &mut changes);
+            }
+        }
+    }
+    changes
+}
+
+/// Whether `diff_all` can match `old` and `new` by document id rather than
+/// by position.
+pub(crate) fn matched_by_id(old: &[OwnedElement], new: &[OwnedElement]) -> bool {
+    by_id(old).is_some() && by_id(new).is_some()
+}
+
+/// `docs` by their id: `metadata.id` for revisions (from `_ql_committed_`
+/// views or `history()`), `documentId` for what DML statements return.
+/// Nothing if a document has no id, or two have the same one.
+fn by_id(docs: &[OwnedElement]) -> Option<BTreeMap<&str, &OwnedElement>> {
+    let mut by_id = BTreeMap::new();
+    for doc in docs {
+        let id = history::metadata_field(doc, "id")
+            .or_else(|| doc.as_struct()?.get("documentId"))?
+            .as_str()?;
+        if by_id.insert(id, doc).is_some() {
+            return None;
+        }
+    }
+    Some(by_id)
+}
+
+fn diff_at(
+    path: &str,
+    old: Option<&OwnedElement>,
+    new: Option<&OwnedElement>,
+    changes: &mut Vec<Change>,
+) {
+    let (old, new) = match (old, new) {
+        (None, None) => return,
+        (None, Some(new)) => {
+            changes.push(Change::Added {
+                path: path.to_string(),
+                value: compact(new),
+            });
+            return;
+        }
+        (Some(old), None) => {
+            changes.push(Change::Removed {
+                path: path.to_string(),
+                value: compact(old),
+            });
+            return;
+        }
+        (Some(old), Some(new)) => (old, new),
+    };
+
+    // Containers are compared item by item below, but a change to their
+    // annotations is only visible on the container itself.
+    let containers = (container(old), container(new));
+    if let (Some(old_type), Some(new_type)) = containers {
+        if old_type == new_type && annotations(old) != annotations(new) {
+            changes.push(Change::Changed {
+                path: path.to_string(),
+                old: compact(old),
+                new: compact(new),
+            });
+        }
+    }
+
+    match containers {
+        (Some(IonType::Struct), Some(IonType::Struct)) => {
+            let (old_struct, new_struct) = (old.as_struct().unwrap(), new.as_struct().unwrap());
+            let names: BTreeSet<_> = old_struct
+                .iter()
+                .chain(new_struct.iter())
+                .filter_map(|(name, _)| name.text().map(|n| n.to_string()))
+                .collect();
+            for name in names {
+                let field_path = match path {
+                    "" => name.clone(),
+                    path => format!("{}.{}", path, name),
+                };
+                diff_at(
+                    &field_path,
+                    old_struct.get(&name),
+                    new_struct.get(&name),
+                    changes,
+                );
+            }
+        }
+        (Some(old_type), Some(new_type)) if old_type == new_type => {
+            let old_items: Vec<_> = old.as_sequence().unwrap().iter().collect();
+            let new_items: Vec<_> = new.as_sequence().unwrap().iter().collect();
+            for i in 0..old_items.len().max(new_items.len()) {
+                diff_at(
+                    &format!("{}[{}]", path, i),
+                    old_items.get(i).copied(),
+                    new_items.get(i).copied(),
+                    changes,
+                );
+            }
+        }
+        _ if !equivalent(old, new) => changes.push(Change::Changed {
+            path: path.to_string(),
+            old: compact(old),
+            new: compact(new),
+        }),
+        _ => {}
+    }
+}
+
+/// The type of `elem` if it is a non-null container.
+fn container(elem: &OwnedElement) -> Option<IonType> {
+    match elem.ion_type() {
+        t @ IonType::Struct | t @ IonType::List | t @ IonType::SExpression if !elem.is_null() => {
+            Some(t)
+        }
+        _ => None,
+    }
+}
+
+fn annotations(elem: &OwnedElement) -> Vec<&str> {
+    elem.annotations()
+        .map(|a| a.text().unwrap_or("$0"))
+        .collect()
+}
+
+/// Whether two scalars are the same Ion value, including their annotations
+/// and precision.
+fn equivalent(old: &OwnedElement, new: &OwnedElement) -> bool {
+    if annotations(old) != annotations(new) || old.is_null() != new.is_null() {
+        return false;
+    }
+    match (old.ion_type(), new.ion_type()) {
+        // Comparing the values would ignore their precision, which their
+        // text keeps.
+        (IonType::Decimal, IonType::Decimal) | (IonType::Timestamp, IonType::Timestamp) => {
+            render(old) == render(new)
+        }
+        _ => old == new,
+    }
+}
+
+/// Renders `elem` on one line, cut short if it is long.
+pub(crate) fn compact(elem: &OwnedElement) -> String {
+    let rendered = render(elem);
+    if rendered.chars().count() <= MAX_VALUE_WIDTH {
+        rendered
+    } else {
+        let cut: String = rendered.chars().take(MAX_VALUE_WIDTH - 3).collect();
+        format!("{}...", cut)
+    }
+}
+
+fn render(elem: &OwnedElement) -> String {
+    results::ion_text(elem).unwrap_or_else(|e| e.to_string())
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Remembers the documents returned by a statement, so that `\diff
+    /// results` can compare the last two.
+    pub(crate) fn remember_results(&mut self, results: &StatementResults) {
+        self.recent_results
+            .push(RecentResults::keep(results.raw_values()));
+        if self.recent_results.len() > 2 {
+            self.recent_results.remove(0);
+        }
+    }
+
+    pub(crate) async fn handle_diff(&self, command: &DiffCommand) -> Result<()> {
+        let changes = match command {
+            DiffCommand::Revisions {
+                table,
+                document_id,
+                old_version,
+                new_version,
+            } => {
+                let revisions = self
+                    .query(&history::history_query(table, document_id, None, None)?)
+                    .await?;
+                let mut data = vec![];
+                for version in &[*old_version, *new_version] {
+                    match revisions.iter().find(|r| history::version(r) == *version) {
+                        // Deleted revisions have no data.
+                        Some(revision) => {
+                            data.push(revision.as_struct().and_then(|r| r.get("data")))
+                        }
+                        None => Err(error::usage_error(format!(
+                            "document '{}' in table '{}' has no version {}",
+                            document_id, table, version
+                        )))?,
+                    }
+                }
+                diff(data[0], data[1])
+            }
+            DiffCommand::Documents {
+                table,
+                old_id,
+                new_id,
+            } => {
+                let old = self.document(table, old_id).await?;
+                let new = self.document(table, new_id).await?;
+                diff(Some(&old), Some(&new))
+            }
+            DiffCommand::Results => match &self.recent_results[..] {
+                [old, new] => {
+                    let (old, new) = (old.elements()?, new.elements()?);
+                    if !matched_by_id(&old, &new) {
+                        self.deps.ui.println(
+                            "comparing by position, the results don't all have a document id \
+                            and QLDB returns them in no particular order",
+                        );
+                    }
+                    diff_all(&old, &new)
+                }
+                _ => Err(error::usage_error(
+                    "there are no results to compare, run two statements first",
+                ))?,
+            },
+        };
+
+        if changes.is_empty() {
+            self.deps.ui.println("no differences");
+        }
+        let color = use_color();
+        for change in changes {
+            match color {
+                true => self.deps.ui.println(&change.colored()),
+                false => self.deps.ui.println(&change.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn document(&self, table: &str, document_id: &str) -> Result<OwnedElement> {
+        let statement = format!(
+            "SELECT * FROM {} AS d BY id WHERE id = {}",
+            quote_identifier(table),
+            quote_string(document_id)
+        );
+        match self.query(&statement).await?.into_iter().next() {
+            Some(doc) => Ok(doc),
+            None => Err(error::usage_error(format!(
+                "no document '{}' in table '{}'",
+                document_id, table
+            )))?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ion_rs::value::reader::{element_reader, ElementReader};
+
+    fn changes(old: &str, new: &str) -> Result<Vec<String>> {
+        let old = element_reader().read_one(old.as_bytes())?;
+        let new = element_reader().read_one(new.as_bytes())?;
+        Ok(diff(Some(&old), Some(&new))
+            .iter()
+            .map(|c| c.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn keeps_recent_results_up_to_a_size() -> Result<()> {
+        let kept = RecentResults::keep(vec![&b"{ a: 1 }"[..]].into_iter());
+        assert_eq!(element_reader().read_all(b"{ a: 1 }")?, kept.elements()?);
+
+        let large = vec![0u8; MAX_RECENT_RESULTS_BYTES];
+        let kept = RecentResults::keep(vec![&large[..], &b"1"[..]].into_iter());
+        assert_eq!(RecentResults::TooLarge(MAX_RECENT_RESULTS_BYTES + 1), kept);
+        assert!(kept.elements().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_paths() -> Result<()> {
+        assert_eq!(
+            vec![
+                "- Color: \"Red\"",
+                "~ Owner.Name: \"a\" -> \"b\"",
+                "+ Tags[1]: \"new\"",
+                "~ Year: 2020 -> \"2020\"",
+            ],
+            changes(
+                r#"{ Year: 2020, Owner: { Name: "a" }, Color: "Red", Tags: ["x"] }"#,
+                r#"{ Year: "2020", Owner: { Name: "b" }, Tags: ["x", "new"] }"#,
+            )?
+        );
+        assert!(changes("{ a: 1 }", "{ a: 1 }")?.is_empty());
+        Ok(())
+    }
+
+    /// How a value is rendered, as Ion C writes it.
+    fn text(ion: &str) -> String {
+        render(&element_reader().read_one(ion.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn whole_values() -> Result<()> {
+        let value = element_reader().read_one(br#"{ a: [1, 2] }"#)?;
+        assert_eq!(
+            vec![format!("+ (value): {}", text("{ a: [1, 2] }"))],
+            diff(None, Some(&value))
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn type_aware() -> Result<()> {
+        for (old, new) in [
+            ("1.0", "1.00"),
+            ("2022T", "2022-01-01T"),
+            ("2022-01-01T00:00:00.10Z", "2022-01-01T00:00:00.1Z"),
+        ] {
+            assert_ne!(text(old), text(new));
+            assert_eq!(
+                vec![format!("~ v: {} -> {}", text(old), text(new))],
+                changes(&format!("{{ v: {} }}", old), &format!("{{ v: {} }}", new))?
+            );
+        }
+        assert_eq!(
+            vec![
+                format!("~ kind: {} -> {}", text("usd::5"), text("eur::5")),
+                format!("~ tags: {} -> {}", text("[1]"), text("x::[1]")),
+            ],
+            changes(
+                "{ kind: usd::5, tags: [1] }",
+                "{ kind: eur::5, tags: x::[1] }"
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compares_results() -> Result<()> {
+        let old = element_reader().read_all(br#"{ a: 1 } { a: 2 }"#)?;
+        let new = element_reader().read_all(br#"{ a: 1 }"#)?;
+        assert_eq!(
+            vec![format!("- [1]: {}", text("{ a: 2 }"))],
+            diff_all(&old, &new)
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert!(!matched_by_id(&old, &new));
+        Ok(())
+    }
+
+    #[test]
+    fn matches_results_by_id() -> Result<()> {
+        let old = element_reader().read_all(
+            br#"{ data: { a: 1 }, metadata: { id: "x" } } { data: { a: 2 }, metadata: { id: "y" } }"#,
+        )?;
+        let new = element_reader().read_all(
+            br#"{ data: { a: 3 }, metadata: { id: "y" } } { data: { a: 1 }, metadata: { id: "x" } }"#,
+        )?;
+        assert!(matched_by_id(&old, &new));
+        assert_eq!(
+            vec![format!("~ [y].data.a: {} -> {}", text("2"), text("3"))],
+            diff_all(&old, &new)
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let old = element_reader().read_all(br#"{ documentId: "x" } { documentId: "y" }"#)?;
+        let new = element_reader().read_all(br#"{ documentId: "y" }"#)?;
+        assert_eq!(
+            vec![format!("- [x]: {}", text(r#"{ documentId: "x" }"#))],
+            diff_all(&old, &new)
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
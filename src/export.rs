@@ -0,0 +1,653 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use amazon_qldb_driver::QldbSession;
+use anyhow::{Context, Result};
+use aws_sdk_qldbsession::error::SendCommandError;
+use aws_sdk_qldbsession::input::{send_command_input, SendCommandInput};
+use aws_sdk_qldbsession::model::{
+    AbortTransactionRequest, CommitTransactionRequest, EndSessionRequest, ExecuteStatementRequest,
+    FetchPageRequest, Page, StartSessionRequest, StartTransactionRequest,
+};
+use aws_sdk_qldbsession::output::SendCommandOutput;
+use aws_sdk_qldbsession::types::{Blob, SdkError};
+use ion_rs::types::integer::IntAccess;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Sequence, Struct};
+
+use crate::error;
+use crate::history::is_table_name;
+use crate::qldb_hash::QldbHash;
+use crate::results::{self, DocumentWriter, ExportFormat};
+use crate::runner::Runner;
+
+/// How far a table export got. It is saved next to the export after every
+/// page, so that an interrupted export can be resumed. The ids of the
+/// documents that were written are kept in a file of their own (see
+/// [`Checkpoint::ids_path`]), a line each, as QLDB returns documents in no
+/// particular order.
+#[derive(Debug, PartialEq, Eq)]
+struct Checkpoint {
+    table: String,
+    format: ExportFormat,
+    documents: usize,
+    /// The size of the export once `documents` were written. Anything after
+    /// that is from a page that wasn't completely written.
+    bytes: u64,
+    /// The size of the ids file once `documents` were written.
+    ids_bytes: u64,
+    /// The CSV columns, which were only written once.
+    columns: Option<Vec<String>>,
+}
+
+impl Checkpoint {
+    fn path(export: &Path) -> PathBuf {
+        let mut path = export.as_os_str().to_owned();
+        path.push(".checkpoint");
+        PathBuf::from(path)
+    }
+
+    fn ids_path(export: &Path) -> PathBuf {
+        let mut path = export.as_os_str().to_owned();
+        path.push(".checkpoint-ids");
+        PathBuf::from(path)
+    }
+
+    fn to_ion(&self) -> String {
+        let columns = match self.columns {
+            Some(ref columns) => {
+                let columns: Vec<_> = columns.iter().map(|c| results::json_string(c)).collect();
+                format!("[{}]", columns.join(", "))
+            }
+            None => "null".to_string(),
+        };
+        format!(
+            "{{ table: {}, format: \"{}\", documents: {}, bytes: {}, ids_bytes: {}, columns: {} }}\n",
+            results::json_string(&self.table),
+            self.format.name(),
+            self.documents,
+            self.bytes,
+            self.ids_bytes,
+            columns
+        )
+    }
+
+    fn from_ion(text: &[u8]) -> Result<Checkpoint> {
+        let invalid = || error::usage_error("the export checkpoint is not valid");
+        let checkpoint = element_reader().read_one(text)?;
+        let checkpoint = checkpoint.as_struct().ok_or_else(invalid)?;
+        let field = |name: &str| checkpoint.get(name).ok_or_else(invalid);
+
+        let columns = match field("columns")?.as_sequence() {
+            Some(columns) => Some(
+                columns
+                    .iter()
+                    .map(|c| c.as_str().map(|c| c.to_string()).ok_or_else(invalid))
+                    .collect::<Result<_, _>>()?,
+            ),
+            None => None,
+        };
+        Ok(Checkpoint {
+            table: field("table")?.as_str().ok_or_else(invalid)?.to_string(),
+            format: field("format")?.as_str().ok_or_else(invalid)?.parse()?,
+            documents: field("documents")?.as_i64().ok_or_else(invalid)? as usize,
+            bytes: field("bytes")?.as_i64().ok_or_else(invalid)? as u64,
+            ids_bytes: field("ids_bytes")?.as_i64().ok_or_else(invalid)? as u64,
+            columns,
+        })
+    }
+}
+
+/// Reads the documents of `table`, with their ids.
+fn table_query(table: &str) -> String {
+    format!(
+        "SELECT r.metadata.id AS id, r.data AS data FROM _ql_committed_{} AS r",
+        table
+    )
+}
+
+fn count_query(table: &str) -> String {
+    format!("SELECT COUNT(*) AS documents FROM _ql_committed_{}", table)
+}
+
+/// The documents of `revisions` (read with [`table_query`]) that weren't
+/// written yet, with their ids, which are added to `written`.
+fn unwritten(
+    revisions: Vec<OwnedElement>,
+    written: &mut HashSet<String>,
+) -> Result<Vec<(String, OwnedElement)>> {
+    let mut documents = vec![];
+    for revision in revisions {
+        let revision = revision.as_struct();
+        let field = |name| revision.and_then(|r| r.get(name));
+        let (id, data) = match (field("id").and_then(|id| id.as_str()), field("data")) {
+            (Some(id), Some(data)) => (id, data),
+            _ => Err(error::bug("a committed revision has no id or data"))?,
+        };
+        if written.insert(id.to_string()) {
+            documents.push((id.to_string(), data.clone()));
+        }
+    }
+    Ok(documents)
+}
+
+/// An export read in several transactions isn't a snapshot of the table:
+/// documents that were deleted after they were written, or inserted (or
+/// changed) before the table was read again, are only caught by counting the
+/// table in the same transaction as its last page was read in.
+fn check_count(table: &str, documents: usize, count: usize) -> Result<()> {
+    if documents != count {
+        Err(error::usage_error(format!(
+            "{} documents were exported, but {} now has {}, it changed while it was exported in several transactions; delete the export and its checkpoint to start over",
+            documents, table, count
+        )))?
+    }
+    Ok(())
+}
+
+/// A session and transaction of our own, used to read results a page at a
+/// time. The driver only returns results once it has fetched every page.
+/// It is also used to commit a statement exactly once: the driver may run a
+/// transaction again if its commit fails.
+pub(crate) struct PagedQuery<'a, C> {
+    client: &'a C,
+    session_token: String,
+    transaction_id: Option<String>,
+    /// The digest of the transaction's statement, which QLDB checks on commit.
+    commit_digest: Option<QldbHash>,
+    first_page: Option<Vec<Vec<u8>>>,
+    next_page_token: Option<String>,
+}
+
+fn page_values(page: Option<Page>) -> (Vec<Vec<u8>>, Option<String>) {
+    match page {
+        Some(page) => (
+            page.values
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|value| value.ion_binary)
+                .map(|value| value.into_inner())
+                .collect(),
+            page.next_page_token,
+        ),
+        None => (vec![], None),
+    }
+}
+
+impl<'a, C> PagedQuery<'a, C>
+where
+    C: QldbSession + Send + Sync,
+{
+    pub(crate) async fn start(
+        client: &'a C,
+        ledger: &str,
+        statement: &str,
+    ) -> Result<PagedQuery<'a, C>> {
+        let mut query = PagedQuery::start_session(client, ledger).await?;
+        match query.execute(statement).await {
+            Ok(()) => Ok(query),
+            Err(e) => {
+                query.finish().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Starts a session without a transaction, see [`PagedQuery::execute`].
+    pub(crate) async fn start_session(client: &'a C, ledger: &str) -> Result<PagedQuery<'a, C>> {
+        let session = client
+            .send_command(
+                SendCommandInput::builder()
+                    .start_session(StartSessionRequest::builder().ledger_name(ledger).build())
+                    .build()?,
+            )
+            .await?;
+        let session_token = match session.start_session.and_then(|r| r.session_token) {
+            Some(session_token) => session_token,
+            None => Err(error::bug("start session did not return a session token"))?,
+        };
+
+        Ok(PagedQuery {
+            client,
+            session_token,
+            transaction_id: None,
+            commit_digest: None,
+            first_page: None,
+            next_page_token: None,
+        })
+    }
+
+    async fn send<F>(&self, command: F) -> Result<SendCommandOutput>
+    where
+        F: FnOnce(send_command_input::Builder) -> send_command_input::Builder,
+    {
+        let input = command(SendCommandInput::builder().session_token(&self.session_token));
+        Ok(self.client.send_command(input.build()?).await?)
+    }
+
+    /// Starts a transaction and executes `statement` in it.
+    pub(crate) async fn execute(&mut self, statement: &str) -> Result<()> {
+        let started = self
+            .send(|b| b.start_transaction(StartTransactionRequest::builder().build()))
+            .await?;
+        let transaction_id = match started.start_transaction.and_then(|r| r.transaction_id) {
+            Some(transaction_id) => transaction_id,
+            None => Err(error::bug(
+                "start transaction did not return a transaction id",
+            ))?,
+        };
+        self.commit_digest = Some(QldbHash::of_ion_string(&transaction_id));
+        self.transaction_id = Some(transaction_id);
+        self.execute_in_transaction(statement).await
+    }
+
+    /// Executes `statement` in the transaction started by
+    /// [`PagedQuery::execute`]. Pages of the previous statement that weren't
+    /// read are dropped.
+    async fn execute_in_transaction(&mut self, statement: &str) -> Result<()> {
+        let transaction_id = match self.transaction_id {
+            Some(ref transaction_id) => transaction_id.clone(),
+            None => Err(error::bug("there is no transaction to execute in"))?,
+        };
+        self.commit_digest = self
+            .commit_digest
+            .take()
+            .map(|digest| digest.dot(&QldbHash::of_ion_string(statement)));
+
+        let executed = self
+            .send(|b| {
+                b.execute_statement(
+                    ExecuteStatementRequest::builder()
+                        .transaction_id(transaction_id)
+                        .statement(statement)
+                        .build(),
+                )
+            })
+            .await?;
+        let (values, next_page_token) =
+            page_values(executed.execute_statement.and_then(|r| r.first_page));
+        self.first_page = Some(values);
+        self.next_page_token = next_page_token;
+        Ok(())
+    }
+
+    /// The values of the next page, or `None` once every page was read.
+    pub(crate) async fn next_page(&mut self) -> Result<Option<Vec<Vec<u8>>>> {
+        if let Some(values) = self.first_page.take() {
+            return Ok(Some(values));
+        }
+        let (token, transaction_id) = match (self.next_page_token.take(), &self.transaction_id) {
+            (Some(token), Some(transaction_id)) => (token, transaction_id.clone()),
+            _ => return Ok(None),
+        };
+        let fetched = self
+            .send(|b| {
+                b.fetch_page(
+                    FetchPageRequest::builder()
+                        .transaction_id(transaction_id)
+                        .next_page_token(token)
+                        .build(),
+                )
+            })
+            .await?;
+        let (values, next_page_token) = page_values(fetched.fetch_page.and_then(|r| r.page));
+        self.next_page_token = next_page_token;
+        Ok(Some(values))
+    }
+
+    /// Commits the transaction started by [`PagedQuery::execute`]. The
+    /// commit is sent once, so if it fails the transaction may or may not
+    /// have been committed, see [`commit_outcome_unknown`].
+    pub(crate) async fn commit(&mut self) -> Result<()> {
+        let (transaction_id, commit_digest) =
+            match (self.transaction_id.take(), self.commit_digest.take()) {
+                (Some(transaction_id), Some(commit_digest)) => (transaction_id, commit_digest),
+                _ => return Err(error::bug("there is no transaction to commit").into()),
+            };
+        self.first_page = None;
+        self.next_page_token = None;
+        self.send(|b| {
+            b.commit_transaction(
+                CommitTransactionRequest::builder()
+                    .transaction_id(transaction_id)
+                    .commit_digest(Blob::new(commit_digest.bytes()))
+                    .build(),
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// An open transaction is aborted rather than committed. Failures are
+    /// ignored, QLDB cleans up eventually.
+    pub(crate) async fn finish(self) {
+        if self.transaction_id.is_some() {
+            let _ = self
+                .send(|b| b.abort_transaction(AbortTransactionRequest::builder().build()))
+                .await;
+        }
+        let _ = self
+            .send(|b| b.end_session(EndSessionRequest::builder().build()))
+            .await;
+    }
+}
+
+/// Whether a failed [`PagedQuery::commit`] may have committed anyway: the
+/// commit may have reached QLDB, but its answer was lost.
+pub(crate) fn commit_outcome_unknown(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<SdkError<SendCommandError>>(),
+        Some(SdkError::TimeoutError(_))
+            | Some(SdkError::DispatchFailure(_))
+            | Some(SdkError::ResponseError { .. })
+    )
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Exports a table (every document in it) or the results of a query to
+    /// `path`, a page at a time.
+    pub(crate) async fn handle_export(
+        &self,
+        source: &str,
+        path: &Path,
+        format: ExportFormat,
+    ) -> Result<()> {
+        match is_table_name(source) {
+            true => self.export_table(source, path, format).await,
+            false => self.export_query(source, path, format).await,
+        }
+    }
+
+    /// Tables are read in a single transaction, a page at a time. If that
+    /// is interrupted (QLDB expires transactions after 30 seconds), running
+    /// the export again reads the table in a new transaction, writing only
+    /// the documents that weren't written yet.
+    async fn export_table(&self, table: &str, path: &Path, format: ExportFormat) -> Result<()> {
+        let checkpoint_path = Checkpoint::path(path);
+        let ids_path = Checkpoint::ids_path(path);
+        let resumed = match fs::read(&checkpoint_path) {
+            Ok(text) => {
+                let checkpoint = Checkpoint::from_ion(&text)?;
+                if checkpoint.table != table || checkpoint.format != format {
+                    Err(error::usage_error(format!(
+                        "{} is the checkpoint of a different export, delete it to start over",
+                        checkpoint_path.display()
+                    )))?
+                }
+                Some(checkpoint)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Err(e)?,
+        };
+
+        let (file, ids_file, mut written) = match resumed {
+            Some(ref checkpoint) => {
+                let mut file = OpenOptions::new().write(true).open(path)?;
+                file.set_len(checkpoint.bytes)?;
+                file.seek(SeekFrom::End(0))?;
+                let mut ids_file = OpenOptions::new().read(true).write(true).open(&ids_path)?;
+                ids_file.set_len(checkpoint.ids_bytes)?;
+                let mut ids = String::new();
+                ids_file.read_to_string(&mut ids)?;
+                let written: HashSet<String> = ids.lines().map(|id| id.to_string()).collect();
+                if written.len() != checkpoint.documents {
+                    Err(error::usage_error(format!(
+                        "{} doesn't match {}, delete both to start over",
+                        ids_path.display(),
+                        checkpoint_path.display()
+                    )))?
+                }
+                self.deps.ui.println(&format!(
+                    "resuming the export to {} after {} documents",
+                    path.display(),
+                    checkpoint.documents
+                ));
+                (file, ids_file, written)
+            }
+            None => (
+                File::create(path)?,
+                File::create(&ids_path)?,
+                HashSet::new(),
+            ),
+        };
+        let mut checkpoint = resumed.unwrap_or(Checkpoint {
+            table: table.to_string(),
+            format,
+            documents: 0,
+            bytes: 0,
+            ids_bytes: 0,
+            columns: None,
+        });
+        let resuming = checkpoint.documents > 0;
+        let mut writer =
+            DocumentWriter::new(format, BufWriter::new(file), checkpoint.columns.clone());
+        let mut ids = BufWriter::new(ids_file);
+
+        let ledger = self.deps.env.current_ledger().name.clone();
+        let exported = async {
+            let statement = table_query(table);
+            let mut query = PagedQuery::start(&self.deps.client, &ledger, &statement).await?;
+            let read = self
+                .export_table_pages(
+                    &mut query,
+                    &mut writer,
+                    &mut ids,
+                    &mut checkpoint,
+                    &mut written,
+                    resuming,
+                    path,
+                )
+                .await;
+            query.finish().await;
+            read
+        }
+        .await;
+        self.deps.ui.newline();
+        exported.with_context(|| {
+            format!(
+                "the export to {} was interrupted after {} documents, run the same \\export again to resume it",
+                path.display(),
+                checkpoint.documents
+            )
+        })?;
+
+        for done in &[checkpoint_path, ids_path] {
+            if let Err(e) = fs::remove_file(done) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    Err(e)?
+                }
+            }
+        }
+        self.deps.ui.println(&format!(
+            "exported {} documents to {}",
+            checkpoint.documents,
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Writes the documents of every page that weren't `written` yet. When
+    /// `resuming`, the table is counted afterwards, see [`check_count`].
+    #[allow(clippy::too_many_arguments)]
+    async fn export_table_pages(
+        &self,
+        query: &mut PagedQuery<'_, C>,
+        writer: &mut DocumentWriter<BufWriter<File>>,
+        ids: &mut BufWriter<File>,
+        checkpoint: &mut Checkpoint,
+        written: &mut HashSet<String>,
+        resuming: bool,
+        path: &Path,
+    ) -> Result<()> {
+        while let Some(values) = query.next_page().await? {
+            let documents = unwritten(results::parse_values(values.iter())?, written)?;
+            if documents.is_empty() {
+                continue;
+            }
+            let (page_ids, documents): (Vec<_>, Vec<_>) = documents.into_iter().unzip();
+
+            writer.write_page(&documents)?;
+            for id in &page_ids {
+                writeln!(ids, "{}", id)?;
+            }
+            ids.flush()?;
+            checkpoint.documents += documents.len();
+            checkpoint.bytes = fs::metadata(path)?.len();
+            checkpoint.ids_bytes = fs::metadata(Checkpoint::ids_path(path))?.len();
+            checkpoint.columns = writer.columns().map(|c| c.to_vec());
+            fs::write(Checkpoint::path(path), checkpoint.to_ion())?;
+            self.print_progress(checkpoint.documents, checkpoint.bytes);
+        }
+
+        if resuming {
+            query
+                .execute_in_transaction(&count_query(&checkpoint.table))
+                .await?;
+            let count = match query.next_page().await? {
+                Some(values) => results::parse_values(values.iter())?
+                    .first()
+                    .and_then(|c| c.as_struct()?.get("documents")?.as_i64()),
+                None => None,
+            };
+            match count {
+                Some(count) => {
+                    check_count(&checkpoint.table, checkpoint.documents, count as usize)?
+                }
+                None => Err(error::bug(
+                    "counting the documents of a table returned no count",
+                ))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The results of a query are read in a single transaction. They may have
+    /// changed by the time an interrupted export is run again, so it starts
+    /// over rather than resuming.
+    async fn export_query(&self, statement: &str, path: &Path, format: ExportFormat) -> Result<()> {
+        let checkpoint_path = Checkpoint::path(path);
+        if checkpoint_path.exists() {
+            Err(error::usage_error(format!(
+                "{} is the checkpoint of a table export, delete it to start over",
+                checkpoint_path.display()
+            )))?
+        }
+
+        let file = File::create(path)?;
+        let mut writer = DocumentWriter::new(format, BufWriter::new(file), None);
+        let ledger = self.deps.env.current_ledger().name.clone();
+        let mut query = PagedQuery::start(&self.deps.client, &ledger, statement).await?;
+        let mut documents = 0;
+        let exported: Result<()> = async {
+            while let Some(values) = query.next_page().await? {
+                writer.write_page(&results::parse_values(values.iter())?)?;
+                documents += values.len();
+                self.print_progress(documents, fs::metadata(path)?.len());
+            }
+            Ok(())
+        }
+        .await;
+        query.finish().await;
+        self.deps.ui.newline();
+        exported.with_context(|| {
+            format!(
+                "the export to {} was interrupted after {} documents, the results of a query can't be resumed so run it again to start over",
+                path.display(),
+                documents
+            )
+        })?;
+
+        self.deps.ui.println(&format!(
+            "exported {} documents to {}",
+            documents,
+            path.display()
+        ));
+        Ok(())
+    }
+
+    fn print_progress(&self, documents: usize, bytes: u64) {
+        self.deps.ui.print(&format!(
+            "\rexported {} documents ({} bytes)",
+            documents, bytes
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints() -> Result<()> {
+        let checkpoint = Checkpoint {
+            table: "Vehicle".to_string(),
+            format: ExportFormat::Csv,
+            documents: 200,
+            bytes: 12345,
+            ids_bytes: 4600,
+            columns: Some(vec!["VIN".to_string(), "Make".to_string()]),
+        };
+        assert_eq!(
+            checkpoint,
+            Checkpoint::from_ion(checkpoint.to_ion().as_bytes())?
+        );
+        assert_eq!(
+            PathBuf::from("out/vehicles.csv.checkpoint"),
+            Checkpoint::path(Path::new("out/vehicles.csv"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn table_queries() {
+        assert_eq!(
+            "SELECT r.metadata.id AS id, r.data AS data FROM _ql_committed_Vehicle AS r",
+            table_query("Vehicle")
+        );
+        assert_eq!(
+            "SELECT COUNT(*) AS documents FROM _ql_committed_Vehicle",
+            count_query("Vehicle")
+        );
+    }
+
+    fn page(ids: &[&str]) -> Vec<OwnedElement> {
+        let text: Vec<_> = ids
+            .iter()
+            .map(|id| format!("{{ id: \"{}\", data: {{ n: \"{}\" }} }}", id, id))
+            .collect();
+        element_reader()
+            .read_all(text.join(" ").as_bytes())
+            .unwrap()
+    }
+
+    fn ids(documents: Vec<(String, OwnedElement)>) -> Vec<String> {
+        documents.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn resumes_whatever_the_order() -> Result<()> {
+        // The first transaction is interrupted after a page...
+        let mut written = HashSet::new();
+        assert_eq!(
+            vec!["c", "a"],
+            ids(unwritten(page(&["c", "a"]), &mut written)?)
+        );
+
+        // ...and the next one returns the table in another order. Document b,
+        // which sorts before the last one written, is still exported.
+        let mut documents = ids(unwritten(page(&["b", "c"]), &mut written)?);
+        documents.extend(ids(unwritten(page(&["a", "d"]), &mut written)?));
+        assert_eq!(vec!["b", "d"], documents);
+        check_count("Vehicle", written.len(), 4)?;
+
+        // A document deleted after it was written is still in the export.
+        assert!(check_count("Vehicle", written.len(), 3).is_err());
+        Ok(())
+    }
+}
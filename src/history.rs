@@ -0,0 +1,167 @@
+use amazon_qldb_driver::QldbSession;
+use anyhow::Result;
+use ion_rs::types::integer::IntAccess;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::{Element, Struct};
+
+use crate::diff;
+use crate::runner::Runner;
+use crate::schema::quote_string;
+use crate::{error, results};
+
+/// QLDB didn't exist before this, so it is a safe start time when only an
+/// end time is given.
+const EARLIEST: &str = "2019T";
+
+/// QLDB table names are letters, digits and underscores, so they can be
+/// used in `history()` without quoting.
+pub(crate) fn is_table_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Builds the `history()` query for one document. `from` and `to` are Ion
+/// timestamps, e.g. `2022-01-31T00:00:00Z`.
+pub(crate) fn history_query(
+    table: &str,
+    document_id: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<String> {
+    if !is_table_name(table) {
+        Err(error::usage_error(format!(
+            "'{}' is not a table name",
+            table
+        )))?
+    }
+    for ts in from.iter().chain(to.iter()) {
+        if ts.is_empty() || ts.contains(|c: char| c == '`' || c.is_whitespace()) {
+            Err(error::usage_error(format!(
+                "'{}' is not a timestamp, e.g. 2022-01-31T00:00:00Z",
+                ts
+            )))?
+        }
+    }
+
+    let args = match (from, to) {
+        (None, None) => table.to_string(),
+        (Some(from), None) => format!("{}, `{}`", table, from),
+        (from, Some(to)) => format!("{}, `{}`, `{}`", table, from.unwrap_or(EARLIEST), to),
+    };
+    Ok(format!(
+        "SELECT * FROM history({}) AS h WHERE h.metadata.id = {}",
+        args,
+        quote_string(document_id)
+    ))
+}
+
+pub(crate) fn metadata_field<'a>(
+    revision: &'a OwnedElement,
+    name: &str,
+) -> Option<&'a OwnedElement> {
+    revision
+        .as_struct()?
+        .get("metadata")?
+        .as_struct()?
+        .get(name)
+}
+
+pub(crate) fn version(revision: &OwnedElement) -> i64 {
+    metadata_field(revision, "version")
+        .and_then(|v| v.as_i64())
+        .unwrap_or_default()
+}
+
+/// Describes each revision (which must be sorted by version) as a diff
+/// against the revision before it.
+pub(crate) fn revision_lines(revisions: &[OwnedElement], color: bool) -> Result<Vec<String>> {
+    let mut lines = vec![];
+    let mut previous: Option<&OwnedElement> = None;
+    for revision in revisions {
+        let field = |name: &str| {
+            metadata_field(revision, name)
+                .map(|value| results::format_element_for_cell(Some(value)))
+                .transpose()
+                .map(|value| value.unwrap_or_default())
+        };
+        lines.push(format!(
+            "version {}  txTime: {}  txId: {}",
+            version(revision),
+            field("txTime")?,
+            field("txId")?,
+        ));
+
+        // Deleting a document creates a revision without data.
+        let data = revision.as_struct().and_then(|r| r.get("data"));
+        match (previous, data) {
+            (Some(_), None) => lines.push("  (deleted)".to_string()),
+            _ => {
+                for change in diff::diff(previous, data) {
+                    let change = match color {
+                        true => change.colored(),
+                        false => change.to_string(),
+                    };
+                    lines.push(format!("  {}", change));
+                }
+            }
+        }
+        previous = data;
+    }
+    Ok(lines)
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Shows every revision of a document, each as a diff against the
+    /// revision before it.
+    pub(crate) async fn handle_history_of(
+        &self,
+        table: &str,
+        document_id: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<()> {
+        let mut revisions = self
+            .query(&history_query(table, document_id, from, to)?)
+            .await?;
+        if revisions.is_empty() {
+            Err(error::usage_error(format!(
+                "no revisions of document '{}' in table '{}'",
+                document_id, table
+            )))?
+        }
+        revisions.sort_by_key(version);
+
+        for line in revision_lines(&revisions, diff::use_color())? {
+            self.deps.ui.println(&line);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_history_queries() -> Result<()> {
+        assert_eq!(
+            "SELECT * FROM history(Vehicle) AS h WHERE h.metadata.id = 'abc'",
+            history_query("Vehicle", "abc", None, None)?
+        );
+        assert_eq!(
+            "SELECT * FROM history(Vehicle, `2019T`, `2022-01-31T`) AS h WHERE h.metadata.id = 'it''s'",
+            history_query("Vehicle", "it's", None, Some("2022-01-31T"))?
+        );
+        assert!(history_query("Vehicle; drop", "abc", None, None).is_err());
+        assert!(history_query("Vehicle", "abc", Some("`"), None).is_err());
+        Ok(())
+    }
+}
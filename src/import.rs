@@ -0,0 +1,510 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use amazon_qldb_driver::QldbSession;
+use anyhow::{Context, Result};
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Struct, SymbolToken};
+use ion_rs::IonType;
+
+use crate::batch::MAX_DOCUMENTS_PER_TRANSACTION;
+use crate::command::ImportCommand;
+use crate::error::{usage_error, ShellError};
+use crate::export::{commit_outcome_unknown, PagedQuery};
+use crate::history::is_table_name;
+use crate::results::{format_element_for_cell, ion_literal, ion_struct, ion_text, ExportFormat};
+use crate::runner::Runner;
+use crate::schema::quote_identifier;
+
+/// QLDB rejects transactions larger than this. Batches are kept under it by
+/// the size of their Ion text, which is larger than the Ion binary QLDB
+/// counts.
+const MAX_BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// The types a field can be converted to with `--type FIELD=TYPE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Int,
+    Decimal,
+    Float,
+    Bool,
+    Timestamp,
+    /// Any Ion (or JSON) value, e.g. a CSV column that holds JSON.
+    Ion,
+}
+
+impl FieldType {
+    fn name(&self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Int => "int",
+            FieldType::Decimal => "decimal",
+            FieldType::Float => "float",
+            FieldType::Bool => "bool",
+            FieldType::Timestamp => "timestamp",
+            FieldType::Ion => "ion",
+        }
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = ShellError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match &s.to_lowercase()[..] {
+            "string" => FieldType::String,
+            "int" => FieldType::Int,
+            "decimal" => FieldType::Decimal,
+            "float" => FieldType::Float,
+            "bool" => FieldType::Bool,
+            "timestamp" => FieldType::Timestamp,
+            "ion" | "json" => FieldType::Ion,
+            _ => return Err(usage_error(format!("{} is not a valid field type", s))),
+        })
+    }
+}
+
+/// Guesses the format of a file from its extension.
+fn format_of(path: &Path) -> ExportFormat {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => ExportFormat::Csv,
+        Some("jsonl") | Some("json") | Some("ndjson") => ExportFormat::Jsonl,
+        Some("10n") => ExportFormat::IonBinary,
+        _ => ExportFormat::Ion,
+    }
+}
+
+/// Parses `text` as a single Ion value.
+fn parse_value(text: &str) -> Option<OwnedElement> {
+    let mut values = element_reader().read_all(text.as_bytes()).ok()?;
+    match values.len() {
+        1 => values.pop(),
+        _ => None,
+    }
+}
+
+/// Converts `text` (e.g. a CSV cell or a JSON string) to `field_type`.
+fn coerce_text(text: &str, field_type: FieldType) -> Result<OwnedElement> {
+    let invalid = || usage_error(format!("'{}' is not a valid {}", text, field_type.name()));
+    if field_type == FieldType::String {
+        return Ok(OwnedElement::from(text.to_string()));
+    }
+    let value = parse_value(text.trim()).ok_or_else(invalid)?;
+    Ok(match (field_type, value.ion_type()) {
+        (FieldType::Ion, _) => value,
+        _ if value.is_null() => Err(invalid())?,
+        (FieldType::Int, IonType::Integer)
+        | (FieldType::Decimal, IonType::Decimal)
+        | (FieldType::Float, IonType::Float)
+        | (FieldType::Bool, IonType::Boolean)
+        | (FieldType::Timestamp, IonType::Timestamp) => value,
+        (FieldType::Decimal, IonType::Integer) => {
+            parse_value(&format!("{}d0", format_element_for_cell(Some(&value))?))
+                .ok_or_else(invalid)?
+        }
+        (FieldType::Float, IonType::Integer) | (FieldType::Float, IonType::Decimal) => {
+            let f: f64 = format_element_for_cell(Some(&value))?
+                .parse()
+                .map_err(|_| invalid())?;
+            OwnedElement::from(f)
+        }
+        _ => Err(invalid())?,
+    })
+}
+
+/// Converts a value to `field_type`. Nulls are left alone.
+fn coerce(elem: &OwnedElement, field_type: FieldType) -> Result<OwnedElement> {
+    if elem.is_null() {
+        return Ok(elem.clone());
+    }
+    match elem.ion_type() {
+        IonType::String => coerce_text(elem.as_str().unwrap(), field_type),
+        _ if field_type == FieldType::String => {
+            Ok(OwnedElement::from(format_element_for_cell(Some(elem))?))
+        }
+        _ => coerce_text(&ion_text(elem)?, field_type),
+    }
+}
+
+/// The value of a CSV cell without a `--type`: booleans and numbers are
+/// recognized, anything else is a string. Empty cells are left out.
+fn infer_cell(cell: &str) -> Option<OwnedElement> {
+    if cell.is_empty() {
+        return None;
+    }
+    if cell == "true" || cell == "false" {
+        return Some(OwnedElement::from(cell == "true"));
+    }
+    let numeric = cell
+        .chars()
+        .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+    if numeric {
+        if let Some(value) = parse_value(cell) {
+            match value.ion_type() {
+                IonType::Integer | IonType::Decimal | IonType::Float => return Some(value),
+                _ => {}
+            }
+        }
+    }
+    Some(OwnedElement::from(cell.to_string()))
+}
+
+/// Splits CSV text (RFC 4180: quoted fields may contain commas, quotes and
+/// line breaks) into records. Blank lines are skipped.
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        Err(usage_error("the CSV ends inside a quoted field"))?
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    Ok(records)
+}
+
+/// Reads every document in `input` and converts it to an Ion literal,
+/// applying `types`. This validates the whole input before anything is
+/// inserted.
+fn read_documents(
+    input: &[u8],
+    format: ExportFormat,
+    types: &[(String, FieldType)],
+) -> Result<Vec<String>> {
+    let field_type = |name: &str| types.iter().find(|(f, _)| f == name).map(|(_, t)| *t);
+
+    let elements = match format {
+        ExportFormat::Csv => {
+            let text = std::str::from_utf8(input).context("the CSV is not UTF-8")?;
+            let mut records = csv_records(text)?.into_iter();
+            let columns = records.next().unwrap_or_default();
+            let mut documents = vec![];
+            for (i, record) in records.enumerate() {
+                if record.len() > columns.len() {
+                    Err(usage_error(format!(
+                        "row {} has {} fields, but there are only {} columns",
+                        i + 1,
+                        record.len(),
+                        columns.len()
+                    )))?
+                }
+                let mut fields = vec![];
+                for (column, cell) in columns.iter().zip(record.iter()) {
+                    let value = match field_type(column) {
+                        Some(_) if cell.is_empty() => None,
+                        Some(t) => {
+                            Some(coerce_text(cell, t).with_context(|| format!("row {}", i + 1))?)
+                        }
+                        None => infer_cell(cell),
+                    };
+                    if let Some(value) = value {
+                        fields.push((&column[..], value));
+                    }
+                }
+                documents.push(ion_literal(&ion_struct(fields))?);
+            }
+            return Ok(documents);
+        }
+        ExportFormat::Jsonl => {
+            let text = std::str::from_utf8(input).context("the JSON is not UTF-8")?;
+            let mut elements = vec![];
+            for (i, line) in text.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // JSON is Ion text, so the Ion reader can parse it.
+                let elem = parse_value(line)
+                    .ok_or_else(|| usage_error(format!("line {} is not a JSON value", i + 1)))?;
+                elements.push(elem);
+            }
+            elements
+        }
+        ExportFormat::Ion | ExportFormat::IonBinary => element_reader().read_all(input)?,
+    };
+
+    let mut documents = vec![];
+    for (i, elem) in elements.iter().enumerate() {
+        let strukt = match elem.as_struct() {
+            Some(strukt) if !elem.is_null() => strukt,
+            _ => Err(usage_error(format!(
+                "document {} is not a struct, QLDB documents must be structs",
+                i + 1
+            )))?,
+        };
+        let mut fields = vec![];
+        for (name, value) in strukt.iter() {
+            let name = name.text().unwrap_or("$0");
+            let value = match field_type(name) {
+                Some(t) => coerce(value, t).with_context(|| format!("document {}", i + 1))?,
+                None => value.clone(),
+            };
+            fields.push((name, value));
+        }
+        documents.push(ion_literal(&ion_struct(fields))?);
+    }
+    Ok(documents)
+}
+
+/// Splits `documents` into batches of at most `size` documents, which also
+/// fit in a transaction.
+fn batches(documents: &[String], size: usize) -> Vec<&[String]> {
+    let mut batches = vec![];
+    let (mut start, mut bytes) = (0, 0);
+    for (i, document) in documents.iter().enumerate() {
+        if i > start && (i - start == size || bytes + document.len() > MAX_BATCH_BYTES) {
+            batches.push(&documents[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += document.len();
+    }
+    if start < documents.len() {
+        batches.push(&documents[start..]);
+    }
+    batches
+}
+
+fn insert_statement(table: &str, documents: &[String]) -> String {
+    let values: Vec<_> = documents.iter().map(|d| format!("`{}`", d)).collect();
+    format!(
+        "INSERT INTO {} << {} >>",
+        quote_identifier(table),
+        values.join(", ")
+    )
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Inserts the documents in a file into a table. Every document is read
+    /// and converted first, so that bad input is found before anything is
+    /// inserted. Each batch is inserted in its own transaction, so a failure
+    /// leaves the batches before it imported.
+    pub(crate) async fn handle_import(&self, command: &ImportCommand) -> Result<()> {
+        if !is_table_name(&command.table) {
+            Err(usage_error(format!(
+                "'{}' is not a table name",
+                command.table
+            )))?
+        }
+        if command.batch == 0 || command.batch > MAX_DOCUMENTS_PER_TRANSACTION {
+            Err(usage_error(format!(
+                "--batch must be between 1 and {}, QLDB allows at most {} documents per transaction",
+                MAX_DOCUMENTS_PER_TRANSACTION, MAX_DOCUMENTS_PER_TRANSACTION
+            )))?
+        }
+
+        let format = command.format.unwrap_or_else(|| format_of(&command.path));
+        let input = fs::read(&command.path)
+            .with_context(|| format!("unable to read {}", command.path.display()))?;
+        let documents = read_documents(&input, format, &command.types)
+            .with_context(|| format!("unable to import {}", command.path.display()))?;
+        if command.skip > documents.len() {
+            Err(usage_error(format!(
+                "--skip {} is past the end of {}, which has {} documents",
+                command.skip,
+                command.path.display(),
+                documents.len()
+            )))?
+        }
+
+        let remaining = &documents[command.skip..];
+        if command.dry_run || self.deps.env.config().ui.dry_run {
+            self.deps.ui.println(&format!(
+                "DRY RUN — {} documents are valid, {} would be inserted into {} in {} transactions",
+                documents.len(),
+                remaining.len(),
+                command.table,
+                batches(remaining, command.batch).len()
+            ));
+            return Ok(());
+        }
+
+        let ledger = self.deps.env.current_ledger().name.clone();
+        let mut session = PagedQuery::start_session(&self.deps.client, &ledger).await?;
+        let inserted = self.insert_batches(&mut session, command, &documents).await;
+        session.finish().await;
+        self.deps.ui.newline();
+        inserted?;
+
+        self.deps.ui.println(&format!(
+            "imported {} documents into {}",
+            remaining.len(),
+            command.table
+        ));
+        Ok(())
+    }
+
+    /// Each batch is committed once, without the driver's retries, so that
+    /// the `--skip` in an error is right: a retried commit could have
+    /// inserted a batch twice.
+    async fn insert_batches(
+        &self,
+        session: &mut PagedQuery<'_, C>,
+        command: &ImportCommand,
+        documents: &[String],
+    ) -> Result<()> {
+        let mut imported = command.skip;
+        for batch in batches(&documents[command.skip..], command.batch) {
+            let statement = insert_statement(&command.table, batch);
+            let range = format!("documents {}-{}", imported + 1, imported + batch.len());
+            if let Err(e) = session.execute(&statement).await {
+                Err(e.context(format!(
+                    "{} were not imported, run the same \\import with --skip {} to resume",
+                    range, imported
+                )))?
+            }
+            if let Err(e) = session.commit().await {
+                let message = match commit_outcome_unknown(&e) {
+                    true => format!(
+                        "QLDB didn't say whether {} were imported, check the table and run the same \\import with --skip {} if they were or --skip {} if they weren't",
+                        range,
+                        imported + batch.len(),
+                        imported
+                    ),
+                    false => format!(
+                        "{} were not imported, run the same \\import with --skip {} to resume",
+                        range, imported
+                    ),
+                };
+                Err(e.context(message))?
+            }
+            imported += batch.len();
+            self.deps.ui.print(&format!(
+                "\rimported {} of {} documents",
+                imported,
+                documents.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(ion: &str) -> OwnedElement {
+        parse_value(ion).unwrap()
+    }
+
+    /// Struct fields aren't kept in order, so documents are compared as Ion.
+    fn documents(literals: &[String]) -> Vec<OwnedElement> {
+        literals.iter().map(|l| element(l)).collect()
+    }
+
+    #[test]
+    fn coercions() -> Result<()> {
+        assert_eq!(element("2020"), coerce_text(" 2020 ", FieldType::Int)?);
+        assert_eq!(element("12d0"), coerce_text("12", FieldType::Decimal)?);
+        assert_eq!(element("1.5e0"), coerce_text("1.5", FieldType::Float)?);
+        assert_eq!(
+            element("2022-01-31T"),
+            coerce_text("2022-01-31T", FieldType::Timestamp)?
+        );
+        // CSV dates are usually written without the T.
+        assert_eq!(
+            element("2022-01-31T"),
+            coerce_text("2022-01-31", FieldType::Timestamp)?
+        );
+        assert!(coerce_text("2022-13-01", FieldType::Timestamp).is_err());
+        assert!(coerce_text("1.5", FieldType::Int).is_err());
+        assert_eq!(
+            element(r#""12""#),
+            coerce(&element("12"), FieldType::String)?
+        );
+        assert_eq!(element("null"), coerce(&element("null"), FieldType::Int)?);
+
+        assert_eq!(None, infer_cell(""));
+        assert_eq!(Some(element("42")), infer_cell("42"));
+        assert_eq!(Some(element(r#""007""#)), infer_cell("007"));
+        assert_eq!(Some(element(r#""1-2""#)), infer_cell("1-2"));
+        assert_eq!(Some(element(r#""nan""#)), infer_cell("nan"));
+        Ok(())
+    }
+
+    #[test]
+    fn reads_csv() -> Result<()> {
+        assert_eq!(
+            vec![vec!["a", "b"], vec!["1", "x,\"y\"\nz"], vec!["", "2"]],
+            csv_records("a,b\r\n1,\"x,\"\"y\"\"\nz\"\n\n,2")?
+        );
+
+        let types = vec![("Year".to_string(), FieldType::String)];
+        let literals = read_documents(
+            b"VIN,Year,Tags\n1N4AL,2019,\"[\"\"a\"\"]\"\n",
+            ExportFormat::Csv,
+            &types,
+        )?;
+        assert_eq!(
+            vec![element(r#"{VIN: "1N4AL", Year: "2019", Tags: "[\"a\"]"}"#)],
+            documents(&literals)
+        );
+        assert!(read_documents(b"a\n1,2\n", ExportFormat::Csv, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_json_and_ion() -> Result<()> {
+        let types = vec![("at".to_string(), FieldType::Timestamp)];
+        let literals = read_documents(
+            b"{\"n\": 1.5, \"at\": \"2022-01-31T\"}\n\n{\"n\": null}\n",
+            ExportFormat::Jsonl,
+            &types,
+        )?;
+        assert_eq!(
+            vec![element("{n: 1.5, at: 2022-01-31T}"), element("{n: null}")],
+            documents(&literals)
+        );
+        let literals = read_documents(b"{s: \"`\"}", ExportFormat::Ion, &[])?;
+        assert!(!literals[0].contains('`'));
+        assert_eq!(vec![element("{s: \"`\"}")], documents(&literals));
+        assert!(read_documents(b"[1]", ExportFormat::Ion, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn batches_documents() {
+        let documents: Vec<_> = (0..5).map(|i| format!("{{a: {}}}", i)).collect();
+        let sizes: Vec<_> = batches(&documents, 2).iter().map(|b| b.len()).collect();
+        assert_eq!(vec![2, 2, 1], sizes);
+
+        let large = vec!["x".repeat(MAX_BATCH_BYTES / 2 + 1); 3];
+        assert_eq!(3, batches(&large, 40).len());
+        assert_eq!(
+            "INSERT INTO \"T\" << `{a: 0}`, `{a: 1}` >>",
+            insert_statement("T", &documents[..2])
+        );
+    }
+}
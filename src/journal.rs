@@ -0,0 +1,413 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ion_rs::types::integer::IntAccess;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Sequence, Struct};
+
+use crate::diff;
+use crate::error;
+use crate::history::{self, metadata_field};
+use crate::qldb_hash::QldbHash;
+use crate::results;
+use crate::settings::{JournalCommand, JournalQuery};
+use crate::verify;
+
+fn field<'a>(elem: &'a OwnedElement, name: &str) -> Option<&'a OwnedElement> {
+    elem.as_struct()?.get(name)
+}
+
+/// Formats a (scalar) field like a table cell, or as nothing if it is
+/// missing.
+fn text(elem: Option<&OwnedElement>) -> Result<String> {
+    Ok(match elem {
+        Some(elem) => results::format_element_for_cell(Some(elem))?,
+        None => String::new(),
+    })
+}
+
+/// Hashes are shown in base64, like `\verify` and `\hash` show them.
+fn hash_text(elem: Option<&OwnedElement>) -> String {
+    match elem.and_then(|e| e.as_bytes()) {
+        Some(bytes) => QldbHash::from_bytes(bytes.to_vec()).to_string(),
+        None => String::new(),
+    }
+}
+
+fn items(elem: Option<&OwnedElement>) -> Vec<&OwnedElement> {
+    match elem.and_then(|e| e.as_sequence()) {
+        Some(items) => items.iter().collect(),
+        None => vec![],
+    }
+}
+
+/// The files of a journal export: one per range of blocks, named
+/// `STRAND.FIRST-LAST.ion`. The manifests are skipped.
+fn journal_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("unable to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            journal_files(&path, files)?;
+            continue;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ion") => files.push(path),
+            // JSON exports keep hashes as base64 strings and lose the Ion
+            // types of the data, so hashes couldn't be checked.
+            Some("json") => Err(error::usage_error(format!(
+                "{} is from a JSON export, which can't be read, export the journal as ION_TEXT or ION_BINARY instead",
+                path.display()
+            )))?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Calls `visit` with every block in the export under `dir`, a file at a
+/// time, so that only what a query needs is kept. Blocks are not in journal
+/// order, see [`block_order`].
+fn for_each_block<F>(dir: &Path, mut visit: F) -> Result<()>
+where
+    F: FnMut(OwnedElement) -> Result<()>,
+{
+    let mut files = vec![];
+    journal_files(dir, &mut files)?;
+
+    let mut found = false;
+    for file in files {
+        let content = fs::read(&file)?;
+        let not_journal = || format!("{} is not a journal file", file.display());
+        for block in element_reader()
+            .iterate_over(&content)
+            .with_context(not_journal)?
+        {
+            visit(block.with_context(not_journal)?)?;
+            found = true;
+        }
+    }
+    if !found {
+        Err(error::usage_error(format!(
+            "found no journal blocks in {}, it should be the directory of a journal export",
+            dir.display()
+        )))?
+    }
+    Ok(())
+}
+
+/// Sorts blocks (or what was kept of them) into journal order.
+fn block_order(block: &OwnedElement) -> (String, i64) {
+    let address = field(block, "blockAddress");
+    (
+        address
+            .and_then(|a| field(a, "strandId"))
+            .and_then(|s| s.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        address
+            .and_then(|a| field(a, "sequenceNo"))
+            .and_then(|s| s.as_i64())
+            .unwrap_or_default(),
+    )
+}
+
+fn address(block: &OwnedElement) -> String {
+    field(block, "blockAddress")
+        .and_then(verify::block_address_text)
+        .unwrap_or_default()
+}
+
+fn statements(block: &OwnedElement) -> Vec<&OwnedElement> {
+    items(field(block, "transactionInfo").and_then(|info| field(info, "statements")))
+}
+
+/// The table a revision is in, from the transaction info of its block.
+fn table_name(block: &OwnedElement, document_id: &str) -> Result<String> {
+    let document = field(block, "transactionInfo")
+        .and_then(|info| field(info, "documents"))
+        .and_then(|documents| field(documents, document_id));
+    text(document.and_then(|d| field(d, "tableName")))
+}
+
+/// Checks a revision's hash, as `\hash` does.
+fn hash_check(revision: &OwnedElement) -> Result<String> {
+    Ok(
+        match (
+            verify::stored_hash(revision),
+            QldbHash::of_revision(revision)?,
+        ) {
+            (Some(stored), Some(computed)) if stored == computed => format!("hash OK ({})", stored),
+            (Some(stored), Some(computed)) => {
+                format!("hash MISMATCH (stored {}, computed {})", stored, computed)
+            }
+            (Some(stored), None) => format!("hash {}", stored),
+            (None, _) => "no hash".to_string(),
+        },
+    )
+}
+
+/// Describes every block, a line each, in journal order.
+fn write_blocks<W: Write>(
+    dir: &Path,
+    describe: fn(&OwnedElement) -> Result<String>,
+    output: &mut W,
+) -> Result<()> {
+    let mut described = vec![];
+    for_each_block(dir, |block| {
+        described.push((block_order(&block), describe(&block)?));
+        Ok(())
+    })?;
+    described.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (_, text) in described {
+        write!(output, "{}", text)?;
+    }
+    Ok(())
+}
+
+fn block_line(block: &OwnedElement) -> Result<String> {
+    Ok(format!(
+        "{}  {}  txId: {}  statements: {}  revisions: {}  hash: {}\n",
+        address(block),
+        text(field(block, "blockTimestamp"))?,
+        text(field(block, "transactionId"))?,
+        statements(block).len(),
+        items(field(block, "revisions")).len(),
+        hash_text(field(block, "blockHash"))
+    ))
+}
+
+fn transaction_lines(block: &OwnedElement) -> Result<String> {
+    let mut lines = format!(
+        "{}  {}\n",
+        text(field(block, "transactionId"))?,
+        text(field(block, "blockTimestamp"))?
+    );
+    for statement in statements(block) {
+        lines.push_str(&format!("  {}\n", text(field(statement, "statement"))?));
+    }
+    Ok(lines)
+}
+
+fn write_transaction<W: Write>(dir: &Path, transaction_id: &str, output: &mut W) -> Result<()> {
+    let mut found = None;
+    for_each_block(dir, |block| {
+        if found.is_none()
+            && field(&block, "transactionId").and_then(|t| t.as_str()) == Some(transaction_id)
+        {
+            found = Some(block);
+        }
+        Ok(())
+    })?;
+    let block = match found {
+        Some(block) => block,
+        None => Err(error::usage_error(format!(
+            "transaction '{}' is not in the export",
+            transaction_id
+        )))?,
+    };
+
+    writeln!(output, "transaction: {}", transaction_id)?;
+    writeln!(output, "block address: {}", address(&block))?;
+    writeln!(
+        output,
+        "block time: {}",
+        text(field(&block, "blockTimestamp"))?
+    )?;
+    writeln!(
+        output,
+        "block hash: {}",
+        hash_text(field(&block, "blockHash"))
+    )?;
+    writeln!(output, "statements:")?;
+    for statement in statements(&block) {
+        writeln!(output, "  {}", text(field(statement, "statement"))?)?;
+    }
+
+    writeln!(output, "revisions:")?;
+    for revision in items(field(&block, "revisions")) {
+        let id = text(metadata_field(revision, "id"))?;
+        // System revisions (e.g. of the table catalog) and redacted ones
+        // have no metadata.
+        let description = match id.is_empty() {
+            true => "revision without metadata".to_string(),
+            false => format!(
+                "document {} version {} (table {})",
+                id,
+                history::version(revision),
+                table_name(&block, &id)?
+            ),
+        };
+        writeln!(output, "  {}: {}", description, hash_check(revision)?)?;
+        if let Some(data) = field(revision, "data") {
+            writeln!(output, "{}", results::pretty_ion_text(data)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_history<W: Write>(
+    dir: &Path,
+    document_id: &str,
+    color: bool,
+    output: &mut W,
+) -> Result<()> {
+    let mut table = String::new();
+    let mut revisions = vec![];
+    for_each_block(dir, |block| {
+        for revision in items(field(&block, "revisions")) {
+            if metadata_field(revision, "id").and_then(|id| id.as_str()) == Some(document_id) {
+                table = table_name(&block, document_id)?;
+                revisions.push(revision.clone());
+            }
+        }
+        Ok(())
+    })?;
+    if revisions.is_empty() {
+        Err(error::usage_error(format!(
+            "document '{}' is not in the export",
+            document_id
+        )))?
+    }
+    revisions.sort_by_key(history::version);
+
+    writeln!(output, "document: {} (table {})", document_id, table)?;
+    for line in history::revision_lines(&revisions, color)? {
+        writeln!(output, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Answers `query` from the journal export in `dir`, without talking to
+/// QLDB.
+pub(crate) fn read_journal<W>(
+    dir: &Path,
+    query: &JournalQuery,
+    color: bool,
+    mut output: W,
+) -> Result<()>
+where
+    W: Write,
+{
+    match query {
+        JournalQuery::Blocks => write_blocks(dir, block_line, &mut output),
+        JournalQuery::Transactions => write_blocks(dir, transaction_lines, &mut output),
+        JournalQuery::Transaction { transaction_id } => {
+            write_transaction(dir, transaction_id, &mut output)
+        }
+        JournalQuery::History { document_id } => {
+            write_history(dir, document_id, color, &mut output)
+        }
+    }
+}
+
+pub(crate) fn query_journal(command: &JournalCommand) -> Result<()> {
+    read_journal(
+        &command.dir,
+        &command.query,
+        diff::use_color(),
+        io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const METADATA: &str = r#"metadata: { id: "doc1", version: VERSION, txTime: 2022-01-31T10:00:00.000Z, txId: "TX" }"#;
+
+    /// A block with one revision, with a correct hash.
+    fn block(sequence_no: i64, transaction_id: &str, version: i64, data: &str) -> Result<String> {
+        let metadata = METADATA
+            .replace("VERSION", &version.to_string())
+            .replace("TX", transaction_id);
+        let revision = format!("{{ data: {}, {} }}", data, metadata);
+        let hash =
+            QldbHash::of_revision(&element_reader().read_one(revision.as_bytes())?)?.unwrap();
+        Ok(format!(
+            r#"{{
+                blockAddress: {{ strandId: "s", sequenceNo: {} }},
+                transactionId: "{}",
+                blockTimestamp: 2022-01-31T10:00:00.000Z,
+                blockHash: {{{{AAEC}}}},
+                transactionInfo: {{
+                    statements: [{{ statement: "UPDATE Vehicle SET Color = 'red'" }}],
+                    documents: {{ doc1: {{ tableName: "Vehicle", statements: [0] }} }}
+                }},
+                revisions: [
+                    {{ hash: {{{{AwQF}}}} }},
+                    {{ blockAddress: {{ strandId: "s", sequenceNo: {} }}, hash: {{{{{}}}}}, data: {}, {} }}
+                ]
+            }}"#,
+            sequence_no,
+            transaction_id,
+            sequence_no,
+            hash.to_base64(),
+            data,
+            metadata
+        ))
+    }
+
+    fn read(dir: &Path, query: JournalQuery) -> Result<String> {
+        let mut output = vec![];
+        read_journal(dir, &query, false, &mut output)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    #[test]
+    fn reads_exports() -> Result<()> {
+        let dir = TempDir::new("journal")?;
+        fs::create_dir(dir.path().join("export"))?;
+        fs::write(
+            dir.path().join("export").join("s.2-2.ion"),
+            block(2, "tx2", 1, r#"{ VIN: "1", Color: "red" }"#)?,
+        )?;
+        fs::write(
+            dir.path().join("s.1-1.ion"),
+            block(1, "tx1", 0, r#"{ VIN: "1", Color: "blue" }"#)?,
+        )?;
+        fs::write(dir.path().join("export.completed.manifest"), "{}")?;
+
+        let blocks = read(dir.path(), JournalQuery::Blocks)?;
+        let lines: Vec<_> = blocks.lines().collect();
+        assert_eq!(2, lines.len());
+        let address = element_reader().read_one(br#"{strandId: "s", sequenceNo: 1}"#)?;
+        assert!(lines[0].starts_with(&verify::block_address_text(&address).unwrap()));
+        assert!(lines[1].ends_with("txId: tx2  statements: 1  revisions: 2  hash: AAEC"));
+
+        let transaction = read(
+            dir.path(),
+            JournalQuery::Transaction {
+                transaction_id: "tx2".to_string(),
+            },
+        )?;
+        assert!(transaction.contains("  UPDATE Vehicle SET Color = 'red'\n"));
+        assert!(transaction.contains("  revision without metadata: hash AwQF"));
+        assert!(transaction.contains("  document doc1 version 1 (table Vehicle): hash OK"));
+
+        let history = read(
+            dir.path(),
+            JournalQuery::History {
+                document_id: "doc1".to_string(),
+            },
+        )?;
+        assert!(history.starts_with("document: doc1 (table Vehicle)\nversion 0"));
+        assert!(history.contains(r#"~ Color: "blue" -> "red""#));
+
+        assert!(read(
+            dir.path(),
+            JournalQuery::History {
+                document_id: "nope".to_string()
+            }
+        )
+        .is_err());
+
+        fs::write(dir.path().join("s.3-3.json"), "{}")?;
+        assert!(read(dir.path(), JournalQuery::Blocks).is_err());
+        Ok(())
+    }
+}
@@ -1,30 +1,65 @@
 use amazon_qldb_driver::{QldbDriver, QldbSession};
 use anyhow::Result;
+use prompt::LastStatus;
 use runner::ProgramFlow;
 use settings::Environment;
 use structopt::StructOpt;
 use thiserror::Error;
 
+use crate::awssdk_driver::CommitDigests;
 use crate::runner::Runner;
-use crate::settings::{Opt, ShellConfig};
+use crate::settings::{Opt, ProofCommand, ShellConfig, Subcommand};
 use crate::ui::ConsoleUi;
 use crate::ui::Ui;
 
 mod awssdk_driver;
+mod batch;
 mod command;
+mod completion;
+mod control_plane;
+mod diff;
 pub mod error;
+mod export;
+mod history;
+mod import;
+mod journal;
+mod prompt;
+mod proof;
+mod qldb_hash;
 mod repl_helper;
 mod results;
 mod runner;
+mod schema;
+mod service_error;
 mod settings;
 mod tracing;
 mod transaction;
 mod ui;
+mod verify;
 
 pub async fn run() -> Result<()> {
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
     let verbose = opt.verbose.clone();
 
+    // Verifying a bundle and reading a journal export are done offline, so
+    // they need neither a ledger nor credentials.
+    if let Some(Subcommand::Proof(ProofCommand::Verify {
+        ref bundle,
+        ref digest,
+        ref digest_tip,
+    })) = opt.command
+    {
+        let trusted = proof::TrustedDigest {
+            digest: digest.clone(),
+            tip_address: digest_tip.clone(),
+        };
+        return proof::verify_bundle_file(bundle, &trusted);
+    }
+    if let Some(Subcommand::Journal(ref journal)) = opt.command {
+        return journal::query_journal(journal);
+    }
+    let mut command = opt.command.take();
+
     let config = match opt.config {
         None => ShellConfig::load_default()?,
         Some(ref path) => ShellConfig::load(path)?,
@@ -42,7 +77,7 @@ pub async fn run() -> Result<()> {
 
     let ui = ConsoleUi::new(env.clone());
 
-    if env.config().ui.display_welcome {
+    if env.config().ui.display_welcome && command.is_none() {
         ui.println(
                 r#"Welcome to the Amazon QLDB Shell!
 
@@ -55,9 +90,12 @@ pub async fn run() -> Result<()> {
 
     loop {
         let client = awssdk_driver::health_check_start_session(&env).await?;
-        let driver = awssdk_driver::build_driver(client, env.current_ledger().name.clone()).await?;
+        let driver =
+            awssdk_driver::build_driver(client.clone(), env.current_ledger().name.clone()).await?;
         let deps = Deps {
             env: env.clone(),
+            commit_digests: client.commit_digests(),
+            client,
             driver,
             ui: Box::new(ui.clone()),
         };
@@ -65,8 +103,18 @@ pub async fn run() -> Result<()> {
         let mut runner = Runner {
             deps,
             current_transaction: None,
+            last_status: LastStatus::None,
+            conflicted_statements: None,
+            batch_progress: Default::default(),
+            parked_transactions: vec![],
+            recent_results: vec![],
+            catalog: Default::default(),
         };
 
+        if let Some(command) = command.take() {
+            return runner.run_subcommand(command).await;
+        }
+
         match runner.start().await? {
             ProgramFlow::Exit => return Ok(()),
             ProgramFlow::Restart => {} // loops!
@@ -79,6 +127,10 @@ where
     C: QldbSession + Send + Sync + Clone + 'static,
 {
     env: Environment,
+    /// Kept so that the driver can be rebuilt if its sessions are lost.
+    client: C,
+    /// What QLDB returned for each commit, see `report_commit`.
+    commit_digests: CommitDigests,
     driver: QldbDriver<C>,
     ui: Box<dyn Ui>,
 }
@@ -90,6 +142,22 @@ enum QldbShellError {
 
     #[error(r"Unknown command, enter 'help' for a list of commands.")]
     UnknownCommand,
+
+    #[error(
+        "Transaction {id} expired after {age_secs}s. QLDB expires transactions that are not \
+        committed within 30 seconds and rejects any changes made in them. Start a new \
+        transaction with 'start transaction' or 'begin' to try again."
+    )]
+    TransactionExpired { id: String, age_secs: u64 },
+
+    #[error(
+        "Transaction {id} was rejected because of an OCC conflict: another transaction changed \
+        data it read. Enter '\\retry' to run its statements again in a new transaction."
+    )]
+    OccConflict { id: String },
+
+    #[error("{0}")]
+    BatchFailed(String),
 }
 
 // FIXME: Make testing support use the core types
@@ -0,0 +1,323 @@
+use chrono::Local;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub(crate) const DEFAULT_PROMPT: &str = "qldb$ACTIVE_TRANSACTION> ";
+
+/// QLDB expires transactions that are not committed within this time.
+pub(crate) const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a transaction can be open before the user is warned about the
+/// upcoming [`TRANSACTION_TIMEOUT`].
+pub(crate) const TRANSACTION_TIMEOUT_WARNING: Duration = Duration::from_secs(20);
+
+/// Everything a prompt template can refer to. This is captured once per tick
+/// (right before the user is asked for input) and handed to the UI alongside
+/// the rendered prompt, so that things like the highlighter can make decisions
+/// based on state rather than on the contents of the prompt.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PromptState {
+    pub(crate) region: String,
+    pub(crate) ledger: String,
+    pub(crate) profile: Option<String>,
+    pub(crate) endpoint: Option<String>,
+    pub(crate) transaction: Option<TransactionState>,
+    pub(crate) last_status: LastStatus,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct TransactionState {
+    pub(crate) id: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) started: Instant,
+    pub(crate) statement_count: usize,
+    /// Shared with the transaction, so that the expiry warning is given once
+    /// whether it comes from the runner or from the timer that runs while the
+    /// user is typing.
+    pub(crate) warned: Arc<AtomicBool>,
+}
+
+impl TransactionState {
+    pub(crate) fn age(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub(crate) fn is_expiring(&self) -> bool {
+        self.age() >= TRANSACTION_TIMEOUT_WARNING
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        self.age() >= TRANSACTION_TIMEOUT
+    }
+
+    /// How long until the transaction [`is_expiring`](Self::is_expiring).
+    pub(crate) fn until_expiring(&self) -> Duration {
+        TRANSACTION_TIMEOUT_WARNING.saturating_sub(self.age())
+    }
+
+    /// The warning that the transaction is about to expire, if it is and the
+    /// user hasn't been warned yet.
+    pub(crate) fn expiry_warning(&self) -> Option<String> {
+        if !self.is_expiring() || self.is_expired() || self.warned.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Some(format!(
+            "warning: transaction {} has been open for {}s and will expire after {}s",
+            self.id.as_deref().unwrap_or_default(),
+            self.age().as_secs(),
+            TRANSACTION_TIMEOUT.as_secs()
+        ))
+    }
+}
+
+/// The outcome of the previous tick, exposed as `$LAST_STATUS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum LastStatus {
+    #[default]
+    None,
+    Ok,
+    Error,
+}
+
+impl LastStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LastStatus::None => "",
+            LastStatus::Ok => "ok",
+            LastStatus::Error => "error",
+        }
+    }
+}
+
+/// A rendered prompt.
+///
+/// `text` is what readline displays (and uses to compute the prompt width).
+/// `styled` is only set if the template contained style markup, in which case
+/// the highlighter should use it verbatim instead of picking a color itself.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Prompt {
+    pub(crate) text: String,
+    pub(crate) styled: Option<String>,
+    pub(crate) state: PromptState,
+}
+
+/// Renders `template` (or the default prompt) against `state`.
+///
+/// The following variables are interpolated: `$REGION`, `$LEDGER`,
+/// `$PROFILE`, `$ENDPOINT`, `$ACTIVE_TRANSACTION`, `$TXID`, `$TX_NAME`,
+/// `$TX_AGE`, `$STMT_COUNT`, `$TIME` and `$LAST_STATUS`. Style markup such as `{green}`
+/// or `{bold}` is then converted to ANSI escape codes (see [`render_markup`]).
+pub(crate) fn build_prompt(template: Option<&str>, state: PromptState) -> Prompt {
+    let template = template.unwrap_or(DEFAULT_PROMPT);
+    let tx = state.transaction.as_ref();
+
+    let interpolated = template
+        .replace("$REGION", &state.region)
+        .replace("$LEDGER", &state.ledger)
+        .replace("$PROFILE", state.profile.as_deref().unwrap_or("default"))
+        .replace("$ENDPOINT", state.endpoint.as_deref().unwrap_or("default"))
+        .replace(
+            "$ACTIVE_TRANSACTION",
+            &match tx {
+                Some(TransactionState {
+                    name: Some(name), ..
+                }) => format!(" *{}:", name),
+                Some(_) => " *".to_string(),
+                None => String::new(),
+            },
+        )
+        .replace("$TXID", tx.and_then(|tx| tx.id.as_deref()).unwrap_or(""))
+        .replace(
+            "$TX_NAME",
+            tx.and_then(|tx| tx.name.as_deref()).unwrap_or(""),
+        )
+        .replace(
+            "$TX_AGE",
+            &tx.map(|tx| format!("{}s", tx.age().as_secs()))
+                .unwrap_or_default(),
+        )
+        .replace(
+            "$STMT_COUNT",
+            &tx.map(|tx| tx.statement_count.to_string())
+                .unwrap_or_default(),
+        )
+        .replace("$TIME", &Local::now().format("%H:%M:%S").to_string())
+        .replace("$LAST_STATUS", state.last_status.as_str());
+
+    let (text, styled) = render_markup(&interpolated);
+    Prompt {
+        text,
+        styled,
+        state,
+    }
+}
+
+fn style_code(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "reset" | "/" => "0",
+        "bold" => "1",
+        "dim" => "2",
+        "underline" => "4",
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        _ => return None,
+    })
+}
+
+/// Splits a prompt containing style markup into its plain text and its
+/// styled (ANSI) form. Markup is a style name in braces, e.g. `{bold}`,
+/// `{green}` or `{reset}` (`{/}` for short). Braces that don't contain a known
+/// style are left alone.
+///
+/// Returns `None` for the styled variant if there was no markup at all.
+fn render_markup(input: &str) -> (String, Option<String>) {
+    let mut plain = String::with_capacity(input.len());
+    let mut styled = String::with_capacity(input.len());
+    let mut found = false;
+    let mut rest = input;
+
+    while let Some(start) = rest.find('{') {
+        let (before, from_brace) = rest.split_at(start);
+        plain.push_str(before);
+        styled.push_str(before);
+
+        let code = from_brace
+            .find('}')
+            .and_then(|end| style_code(&from_brace[1..end]).map(|code| (end, code)));
+        match code {
+            Some((end, code)) => {
+                found = true;
+                styled.push_str(&format!("\x1b[{}m", code));
+                rest = &from_brace[end + 1..];
+            }
+            None => {
+                plain.push('{');
+                styled.push('{');
+                rest = &from_brace[1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    styled.push_str(rest);
+
+    if found {
+        // Never leak styles into the user's input.
+        styled.push_str("\x1b[0m");
+        (plain, Some(styled))
+    } else {
+        (plain, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> PromptState {
+        PromptState {
+            region: "us-east-1".to_string(),
+            ledger: "my-ledger".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_prompt() {
+        let prompt = build_prompt(None, state());
+        assert_eq!("qldb> ", prompt.text);
+        assert!(prompt.styled.is_none());
+
+        let mut in_tx = state();
+        in_tx.transaction = Some(TransactionState {
+            id: Some("txid".to_string()),
+            name: None,
+            started: Instant::now(),
+            statement_count: 2,
+            warned: Default::default(),
+        });
+        let prompt = build_prompt(None, in_tx);
+        assert_eq!("qldb *> ", prompt.text);
+
+        let mut in_named_tx = state();
+        in_named_tx.transaction = Some(TransactionState {
+            id: Some("txid".to_string()),
+            name: Some("t1".to_string()),
+            started: Instant::now(),
+            statement_count: 0,
+            warned: Default::default(),
+        });
+        let prompt = build_prompt(None, in_named_tx);
+        assert_eq!("qldb *t1:> ", prompt.text);
+    }
+
+    #[test]
+    fn interpolates_state() {
+        let mut in_tx = state();
+        in_tx.last_status = LastStatus::Error;
+        in_tx.transaction = Some(TransactionState {
+            id: Some("txid".to_string()),
+            name: Some("t1".to_string()),
+            started: Instant::now(),
+            statement_count: 2,
+            warned: Default::default(),
+        });
+        let prompt = build_prompt(
+            Some("$LEDGER@$REGION [$PROFILE] $TX_NAME=$TXID/$STMT_COUNT/$TX_AGE $LAST_STATUS> "),
+            in_tx,
+        );
+        assert_eq!(
+            "my-ledger@us-east-1 [default] t1=txid/2/0s error> ",
+            prompt.text
+        );
+    }
+
+    #[test]
+    fn warns_once_before_expiry() {
+        let mut tx = TransactionState {
+            id: Some("txid".to_string()),
+            name: None,
+            started: Instant::now(),
+            statement_count: 0,
+            warned: Default::default(),
+        };
+        assert_eq!(None, tx.expiry_warning());
+        assert!(tx.until_expiring() > Duration::from_secs(19));
+
+        tx.started = Instant::now() - Duration::from_secs(25);
+        assert_eq!(Duration::from_secs(0), tx.until_expiring());
+        assert_eq!(
+            Some("warning: transaction txid has been open for 25s and will expire after 30s"),
+            tx.expiry_warning().as_deref()
+        );
+        assert_eq!(None, tx.clone().expiry_warning());
+    }
+
+    #[test]
+    fn asterisks_are_not_special() {
+        let prompt = build_prompt(Some("*** $LEDGER> "), state());
+        assert_eq!("*** my-ledger> ", prompt.text);
+        assert!(prompt.state.transaction.is_none());
+    }
+
+    #[test]
+    fn markup() {
+        let prompt = build_prompt(Some("{bold}{green}$LEDGER{/}> "), state());
+        assert_eq!("my-ledger> ", prompt.text);
+        assert_eq!(
+            Some("\x1b[1m\x1b[32mmy-ledger\x1b[0m> \x1b[0m"),
+            prompt.styled.as_deref()
+        );
+
+        // Unknown markup is left as-is.
+        let prompt = build_prompt(Some("{not-a-style} {"), state());
+        assert_eq!("{not-a-style} {", prompt.text);
+        assert!(prompt.styled.is_none());
+    }
+}
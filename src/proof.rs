@@ -0,0 +1,317 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use amazon_qldb_driver::QldbSession;
+use anyhow::{anyhow, Result};
+use ion_rs::value::owned::{OwnedElement, OwnedStruct};
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Struct};
+
+use crate::error;
+use crate::qldb_hash::QldbHash;
+use crate::results;
+use crate::runner::Runner;
+use crate::verify::{self, ProvenRevision};
+
+/// Renders an offline verification bundle: the revision, its proof, and the
+/// digest the proof leads to. Everything QLDB returned is kept as-is.
+fn bundle_text(table: &str, document_id: &str, proven: &ProvenRevision) -> Result<String> {
+    let string = |s: &str| results::ion_text(&OwnedElement::from(s.to_string()));
+    Ok(format!(
+        "{{\n  ledger: {},\n  table: {},\n  documentId: {},\n  revision: {},\n  proof: {},\n  digest: {{{{{}}}}},\n  digestTipAddress: {},\n}}\n",
+        string(&proven.ledger)?,
+        string(table)?,
+        string(document_id)?,
+        proven.revision_text,
+        proven.proof_text,
+        proven.digest.digest.to_base64(),
+        proven.digest.tip_address
+    ))
+}
+
+fn bundle_field<'a>(bundle: &'a OwnedStruct, name: &str) -> Result<&'a OwnedElement> {
+    match bundle.get(name) {
+        Some(value) => Ok(value),
+        None => Err(error::usage_error(format!("the bundle has no `{}`", name)))?,
+    }
+}
+
+/// A digest the auditor got from QLDB themselves (e.g. with `aws qldb
+/// get-digest`), as opposed to the one in the bundle.
+#[derive(Debug, Default)]
+pub(crate) struct TrustedDigest {
+    /// Base64, as `GetDigest` returns it.
+    pub(crate) digest: Option<String>,
+    /// Ion text, e.g. `{strandId: "...", sequenceNo: 42}`.
+    pub(crate) tip_address: Option<String>,
+}
+
+impl TrustedDigest {
+    fn is_empty(&self) -> bool {
+        self.digest.is_none() && self.tip_address.is_none()
+    }
+
+    /// Fails unless the bundle's digest and tip are the trusted ones.
+    fn check(&self, digest: &QldbHash, tip_address: &OwnedElement) -> Result<()> {
+        if let Some(ref trusted) = self.digest {
+            let trusted = match base64::decode(trusted.trim()) {
+                Ok(bytes) => QldbHash::from_bytes(bytes),
+                Err(e) => Err(error::usage_error(format!(
+                    "--digest should be base64: {}",
+                    e
+                )))?,
+            };
+            if &trusted != digest {
+                Err(anyhow!(
+                    "FAIL: the bundle's digest {} is not the given digest {}",
+                    digest,
+                    trusted
+                ))?
+            }
+        }
+        if let Some(ref trusted) = self.tip_address {
+            let trusted = match element_reader().read_one(trusted.as_bytes()) {
+                Ok(address) => verify::block_address_text(&address),
+                Err(_) => None,
+            };
+            let trusted = match trusted {
+                Some(trusted) => trusted,
+                None => Err(error::usage_error(
+                    "--digest-tip should be a block address, e.g. {strandId: \"...\", sequenceNo: 42}",
+                ))?,
+            };
+            let tip = verify::block_address_text(tip_address).unwrap_or_default();
+            if tip != trusted {
+                Err(anyhow!(
+                    "FAIL: the bundle's digest tip {} is not the given tip {}",
+                    tip,
+                    trusted
+                ))?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies a bundle written by `qldb proof export`, without talking to
+/// QLDB: the revision must hash to its `hash`, and that hash combined with
+/// the proof must lead to the digest. The bundle's digest is only worth
+/// something if it matches a `trusted` one, otherwise the bundle is only
+/// shown to be consistent with itself.
+pub(crate) fn verify_bundle<W>(bundle: &[u8], trusted: &TrustedDigest, mut output: W) -> Result<()>
+where
+    W: Write,
+{
+    let bundle = element_reader().read_one(bundle)?;
+    let bundle = match bundle.as_struct() {
+        Some(bundle) => bundle,
+        None => Err(error::usage_error("the bundle should be an Ion struct"))?,
+    };
+    let field = |name| bundle_field(bundle, name);
+    let text =
+        |name| -> Result<String> { Ok(field(name)?.as_str().unwrap_or_default().to_string()) };
+
+    let revision = field("revision")?;
+    let proof = verify::proof_hashes(field("proof")?)?;
+    let digest = match field("digest")?.as_bytes() {
+        Some(digest) => QldbHash::from_bytes(digest.to_vec()),
+        None => Err(error::usage_error("the bundle's `digest` should be a blob"))?,
+    };
+    let (stored, computed) = match (
+        verify::stored_hash(revision),
+        QldbHash::of_revision(revision)?,
+    ) {
+        (Some(stored), Some(computed)) => (stored, computed),
+        _ => Err(error::usage_error(
+            "the bundle's `revision` should have a `hash` and `metadata`",
+        ))?,
+    };
+
+    writeln!(
+        output,
+        "document: {} (table {}, ledger {})",
+        text("documentId")?,
+        text("table")?,
+        text("ledger")?
+    )?;
+    writeln!(output, "revision hash: {}", stored)?;
+    let tip_address = field("digestTipAddress")?;
+    writeln!(
+        output,
+        "digest: {} (tip: {})",
+        digest,
+        verify::block_address_text(tip_address).unwrap_or_default()
+    )?;
+    trusted.check(&digest, tip_address)?;
+
+    if computed != stored {
+        Err(anyhow!(
+            "FAIL: the revision's data and metadata hash to {}, not to its hash",
+            computed
+        ))?
+    }
+    verify::check_proof(&stored, &proof, &digest)?;
+    if trusted.is_empty() {
+        writeln!(
+            output,
+            "PASS: the revision is proven to be in the bundle's digest\n\
+            NOTE: this only shows that the bundle is consistent with itself. To prove \
+            the revision is in the ledger, get the digest from QLDB yourself and pass \
+            it with --digest (and --digest-tip)"
+        )?;
+    } else {
+        writeln!(
+            output,
+            "PASS: the revision is proven to be in the given ledger digest"
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn verify_bundle_file(path: &Path, trusted: &TrustedDigest) -> Result<()> {
+    let bundle = match fs::read(path) {
+        Ok(bundle) => bundle,
+        Err(e) => Err(error::usage_error(format!(
+            "unable to read {}: {}",
+            path.display(),
+            e
+        )))?,
+    };
+    verify_bundle(&bundle, trusted, io::stdout())
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Writes a bundle that `qldb proof verify` can check offline.
+    pub(crate) async fn export_proof(
+        &self,
+        table: &str,
+        document_id: &str,
+        path: &Path,
+    ) -> Result<()> {
+        let proven = self.prove_revision(table, document_id).await?;
+        let bundle = bundle_text(table, document_id, &proven)?;
+        // Check the bundle the way an auditor would, so that a broken one is
+        // never handed over.
+        verify_bundle(bundle.as_bytes(), &TrustedDigest::default(), io::sink())?;
+        fs::write(path, bundle)?;
+
+        self.deps.ui.println(&format!(
+            "wrote a proof of document {} to {} (digest {})",
+            document_id,
+            path.display(),
+            proven.digest.digest
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_plane::LedgerDigest;
+
+    const METADATA: &str =
+        r#"{ id: "abc", version: 0, txTime: 2022-01-31T12:34:56.789Z, txId: "xyz" }"#;
+
+    fn bundle(data: &str) -> Result<String> {
+        bundle_text("Vehicle", "abc", &proven(data)?)
+    }
+
+    fn proven(data: &str) -> Result<ProvenRevision> {
+        let unhashed = format!(r#"{{ data: {}, metadata: {} }}"#, data, METADATA);
+        let hash =
+            QldbHash::of_revision(&element_reader().read_one(unhashed.as_bytes())?)?.unwrap();
+        let sibling = QldbHash::of_ion_string("sibling");
+
+        Ok(ProvenRevision {
+            ledger: "my-ledger".to_string(),
+            address: r#"{strandId: "s", sequenceNo: 1}"#.to_string(),
+            hash: hash.clone(),
+            revision_text: format!(
+                r#"{{ blockAddress: {{ strandId: "s", sequenceNo: 1 }}, hash: {{{{{}}}}}, data: {{ VIN: "1" }}, metadata: {} }}"#,
+                hash.to_base64(),
+                METADATA
+            ),
+            proof: vec![sibling.clone()],
+            proof_text: format!("[{{{{{}}}}}]", sibling.to_base64()),
+            digest: LedgerDigest {
+                digest: hash.dot(&sibling),
+                tip_address: r#"{strandId: "s", sequenceNo: 2}"#.to_string(),
+            },
+        })
+    }
+
+    #[test]
+    fn verifies_bundles() -> Result<()> {
+        let untrusted = TrustedDigest::default();
+        let mut output = vec![];
+        verify_bundle(
+            bundle(r#"{ VIN: "1" }"#)?.as_bytes(),
+            &untrusted,
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("document: abc (table Vehicle, ledger my-ledger)"));
+        assert!(output.contains("PASS: the revision is proven to be in the bundle's digest\n"));
+        assert!(output.contains("NOTE: this only shows that the bundle is consistent with itself"));
+
+        // The bundle's revision always has VIN "1", so its hash no longer
+        // matches if the hash was computed from other data.
+        let tampered = bundle(r#"{ VIN: "2" }"#)?;
+        assert!(verify_bundle(tampered.as_bytes(), &untrusted, io::sink()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn checks_the_trusted_digest() -> Result<()> {
+        let bundle = bundle(r#"{ VIN: "1" }"#)?;
+        let digest = element_reader().read_one(bundle.as_bytes())?;
+        let digest = base64::encode(
+            digest
+                .as_struct()
+                .unwrap()
+                .get("digest")
+                .unwrap()
+                .as_bytes()
+                .unwrap(),
+        );
+
+        let trusted = TrustedDigest {
+            digest: Some(digest.clone()),
+            tip_address: Some(r#"{ sequenceNo: 2, strandId: "s" }"#.to_string()),
+        };
+        let mut output = vec![];
+        verify_bundle(bundle.as_bytes(), &trusted, &mut output)?;
+        let output = String::from_utf8(output)?;
+        assert!(output.ends_with("PASS: the revision is proven to be in the given ledger digest\n"));
+
+        let other = TrustedDigest {
+            digest: Some(QldbHash::of_ion_string("other").to_base64()),
+            tip_address: None,
+        };
+        let e = verify_bundle(bundle.as_bytes(), &other, io::sink()).unwrap_err();
+        assert!(e.to_string().starts_with("FAIL: the bundle's digest"));
+
+        let later = TrustedDigest {
+            digest: Some(digest),
+            tip_address: Some(r#"{strandId: "s", sequenceNo: 3}"#.to_string()),
+        };
+        let e = verify_bundle(bundle.as_bytes(), &later, io::sink()).unwrap_err();
+        assert!(e.to_string().starts_with("FAIL: the bundle's digest tip"));
+        Ok(())
+    }
+
+    #[test]
+    fn escapes_bundle_strings() -> Result<()> {
+        // Rust would escape the control character as `\u{1}`, which isn't Ion.
+        let bundle = bundle_text("Vé\"\u{1}", "abc", &proven(r#"{ VIN: "1" }"#)?)?;
+        let bundle = element_reader().read_one(bundle.as_bytes())?;
+        let table = bundle.as_struct().unwrap().get("table").unwrap();
+        assert_eq!(Some("Vé\"\u{1}"), table.as_str());
+        Ok(())
+    }
+}
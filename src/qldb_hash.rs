@@ -0,0 +1,553 @@
+use anyhow::Result;
+use chrono::{Datelike, Timelike};
+use ion_c_sys::timestamp::{IonDateTime, Mantissa, TSOffsetKind, TSPrecision};
+use ion_rs::external::bigdecimal::num_bigint::Sign;
+use ion_rs::external::bigdecimal::BigDecimal;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::{Element, Sequence, Struct, SymbolToken};
+use ion_rs::{Integer, IonType};
+use sha2::{Digest, Sha256};
+use std::convert::{TryFrom, TryInto};
+use std::{cmp::Ordering, fmt};
+
+// Markers defined by the Ion Hash specification.
+const BEGIN_MARKER: u8 = 0x0B;
+const END_MARKER: u8 = 0x0E;
+const ESCAPE: u8 = 0x0C;
+
+// Type qualifiers: the type nibble of the Ion binary type descriptor. The
+// qualifier nibble is 0, except for nulls, booleans and negative ints.
+const TQ_SYMBOL: u8 = 0x70;
+const TQ_STRING: u8 = 0x80;
+const TQ_ANNOTATED: u8 = 0xE0;
+const NULL_QUALIFIER: u8 = 0x0F;
+/// The qualifier of symbols with unknown text, such as `$0`.
+const UNKNOWN_TEXT_QUALIFIER: u8 = 0x01;
+
+/// How struct fields are hashed before they are sorted. QLDB uses SHA-256;
+/// the Ion Hash test suite uses an identity "hash" so that the serialization
+/// can be checked byte by byte.
+type FieldHasher = fn(&[u8]) -> Vec<u8>;
+
+fn sha256(bytes: &[u8]) -> Vec<u8> {
+    Sha256::digest(bytes).to_vec()
+}
+
+/// A SHA-256 hash, as used by QLDB for commit digests, revision hashes and
+/// proofs.
+///
+/// QLDB combines hashes with the "dot" operator (see [`QldbHash::dot`]) rather
+/// than by simple concatenation, so the order in which hashes are combined
+/// does not matter.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub(crate) struct QldbHash {
+    bytes: Vec<u8>,
+}
+
+impl QldbHash {
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> QldbHash {
+        QldbHash { bytes }
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes[..]
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The Ion hash of an Ion string value. This is how QLDB hashes
+    /// transaction ids and statements when computing a commit digest.
+    pub(crate) fn of_ion_string(value: &str) -> QldbHash {
+        QldbHash::from_bytes(sha256(&serialize_scalar(TQ_STRING, value.as_bytes())))
+    }
+
+    /// The Ion hash of any Ion value, as defined by the Ion Hash
+    /// specification (with SHA-256).
+    pub(crate) fn of_ion_value(elem: &OwnedElement) -> Result<QldbHash> {
+        Ok(QldbHash::from_bytes(sha256(&serialize(elem, sha256)?)))
+    }
+
+    /// The hash QLDB computes for a revision (e.g. a document from a
+    /// `_ql_committed_` table or `history()`): the hash of its `metadata`
+    /// combined with the hash of its `data`. Deleted revisions have no data.
+    /// `None` if `revision` has no metadata.
+    pub(crate) fn of_revision(revision: &OwnedElement) -> Result<Option<QldbHash>> {
+        let revision = match revision.as_struct() {
+            Some(revision) => revision,
+            None => return Ok(None),
+        };
+        let metadata = match revision.get("metadata") {
+            Some(metadata) => QldbHash::of_ion_value(metadata)?,
+            None => return Ok(None),
+        };
+        let data = match revision.get("data") {
+            Some(data) => QldbHash::of_ion_value(data)?,
+            None => QldbHash::default(),
+        };
+        Ok(Some(metadata.dot(&data)))
+    }
+
+    /// Combines two hashes. The smaller hash (see [`compare_hashes`]) goes
+    /// first, and the concatenation is hashed again. If either side is empty,
+    /// the other side is returned as-is.
+    pub(crate) fn dot(&self, other: &QldbHash) -> QldbHash {
+        if self.is_empty() {
+            return other.clone();
+        }
+        if other.is_empty() {
+            return self.clone();
+        }
+
+        let mut hasher = Sha256::new();
+        match compare_hashes(&self.bytes, &other.bytes) {
+            Ordering::Less => {
+                hasher.update(&self.bytes);
+                hasher.update(&other.bytes);
+            }
+            _ => {
+                hasher.update(&other.bytes);
+                hasher.update(&self.bytes);
+            }
+        }
+        QldbHash::from_bytes(hasher.finalize().to_vec())
+    }
+
+    /// Recomputes a ledger digest from `self` (a revision hash) and the
+    /// hashes of a proof, as returned by `GetRevision`. The revision is
+    /// verified if the result equals the digest.
+    pub(crate) fn with_proof(&self, proof: &[QldbHash]) -> QldbHash {
+        proof.iter().fold(self.clone(), |hash, node| hash.dot(node))
+    }
+
+    pub(crate) fn to_base64(&self) -> String {
+        base64::encode(&self.bytes)
+    }
+}
+
+impl fmt::Display for QldbHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_base64())
+    }
+}
+
+impl fmt::Debug for QldbHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QldbHash({})", self.to_base64())
+    }
+}
+
+/// Orders hashes the way QLDB does: byte by byte starting from the *last*
+/// byte, treating each byte as signed.
+fn compare_hashes(left: &[u8], right: &[u8]) -> Ordering {
+    for (l, r) in left.iter().rev().zip(right.iter().rev()) {
+        match (*l as i8).cmp(&(*r as i8)) {
+            Ordering::Equal => continue,
+            other => return other,
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
+/// `B || tq || escape(representation) || E`, which is how the Ion Hash
+/// specification serializes a scalar without annotations.
+fn serialize_scalar(tq: u8, representation: &[u8]) -> Vec<u8> {
+    let mut serialized = vec![BEGIN_MARKER, tq];
+    serialized.extend(escape(representation));
+    serialized.push(END_MARKER);
+    serialized
+}
+
+/// Serializes `elem` the way the Ion Hash specification does before hashing
+/// it (`s(value)` in the specification). Scalars are represented as in Ion
+/// binary, without the type descriptor and length.
+fn serialize(elem: &OwnedElement, field_hasher: FieldHasher) -> Result<Vec<u8>> {
+    let value = serialize_value(elem, field_hasher)?;
+    let annotations: Vec<_> = elem.annotations().collect();
+    if annotations.is_empty() {
+        return Ok(value);
+    }
+
+    let mut serialized = vec![BEGIN_MARKER, TQ_ANNOTATED];
+    for annotation in annotations {
+        serialized.extend(serialize_symbol(annotation));
+    }
+    serialized.extend(value);
+    serialized.push(END_MARKER);
+    Ok(serialized)
+}
+
+/// Symbols are represented by their text. Symbols without text (only a
+/// symbol id) have no representation, and a qualifier saying so.
+fn serialize_symbol<S: SymbolToken>(symbol: &S) -> Vec<u8> {
+    match symbol.text() {
+        Some(text) => serialize_scalar(TQ_SYMBOL, text.as_bytes()),
+        None => serialize_scalar(TQ_SYMBOL | UNKNOWN_TEXT_QUALIFIER, &[]),
+    }
+}
+
+fn serialize_value(elem: &OwnedElement, field_hasher: FieldHasher) -> Result<Vec<u8>> {
+    let tq = match elem.ion_type() {
+        IonType::Null => 0x00,
+        IonType::Boolean => 0x10,
+        IonType::Integer => 0x20,
+        IonType::Float => 0x40,
+        IonType::Decimal => 0x50,
+        IonType::Timestamp => 0x60,
+        IonType::Symbol => TQ_SYMBOL,
+        IonType::String => TQ_STRING,
+        IonType::Clob => 0x90,
+        IonType::Blob => 0xA0,
+        IonType::List => 0xB0,
+        IonType::SExpression => 0xC0,
+        IonType::Struct => 0xD0,
+    };
+    if elem.is_null() {
+        return Ok(serialize_scalar(tq | NULL_QUALIFIER, &[]));
+    }
+
+    Ok(match elem.ion_type() {
+        IonType::Null => serialize_scalar(tq | NULL_QUALIFIER, &[]),
+        IonType::Boolean => serialize_scalar(tq | elem.as_bool().unwrap() as u8, &[]),
+        // Ints are a magnitude, with the sign in the type.
+        IonType::Integer => match elem.as_integer().unwrap() {
+            Integer::I64(i) if *i < 0 => {
+                serialize_scalar(0x30, trim(&i.unsigned_abs().to_be_bytes()))
+            }
+            Integer::I64(i) => serialize_scalar(tq, trim(&i.to_be_bytes())),
+            Integer::BigInt(i) => {
+                let (sign, magnitude) = i.to_bytes_be();
+                let tq = if sign == Sign::Minus { 0x30 } else { tq };
+                serialize_scalar(tq, trim(&magnitude))
+            }
+        },
+        IonType::Float => {
+            let f = elem.as_f64().unwrap();
+            if f == 0.0 && f.is_sign_positive() {
+                serialize_scalar(tq, &[])
+            } else if f.is_nan() {
+                serialize_scalar(tq, &f64::NAN.to_bits().to_be_bytes())
+            } else {
+                serialize_scalar(tq, &f.to_bits().to_be_bytes())
+            }
+        }
+        IonType::Decimal => serialize_scalar(tq, &decimal_representation(elem)),
+        IonType::Timestamp => {
+            let ts: IonDateTime = elem.as_timestamp().unwrap().clone().try_into()?;
+            serialize_scalar(tq, &timestamp_representation(&ts))
+        }
+        IonType::Symbol => serialize_symbol(elem.as_sym().unwrap()),
+        IonType::String => serialize_scalar(tq, elem.as_str().unwrap().as_bytes()),
+        IonType::Clob | IonType::Blob => serialize_scalar(tq, elem.as_bytes().unwrap()),
+        IonType::List | IonType::SExpression => {
+            let mut serialized = vec![BEGIN_MARKER, tq];
+            for item in elem.as_sequence().unwrap().iter() {
+                serialized.extend(serialize(item, field_hasher)?);
+            }
+            serialized.push(END_MARKER);
+            serialized
+        }
+        // Fields are hashed individually and sorted, so that field order
+        // doesn't matter.
+        IonType::Struct => {
+            let mut fields = vec![];
+            for (name, value) in elem.as_struct().unwrap().iter() {
+                let mut field = serialize_symbol(name);
+                field.extend(serialize(value, field_hasher)?);
+                fields.push(field_hasher(&field));
+            }
+            fields.sort();
+            serialize_scalar(tq, &fields.concat())
+        }
+    })
+}
+
+/// Strips leading zero bytes, so that 0 is represented by nothing.
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// An Ion binary `VarUInt`: 7 bits per byte, the last byte is marked by its
+/// highest bit.
+fn var_uint(mut value: u64) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7F) as u8 | 0x80];
+    value >>= 7;
+    while value > 0 {
+        bytes.insert(0, (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    bytes
+}
+
+/// An Ion binary `VarInt`: a `VarUInt` with the sign in the second highest
+/// bit of the first byte. Negative zero is how unknown offsets are written.
+fn var_int(negative: bool, magnitude: u64) -> Vec<u8> {
+    let mut bytes = var_uint(magnitude);
+    if bytes[0] & 0x40 != 0 {
+        bytes.insert(0, 0);
+    }
+    if negative {
+        bytes[0] |= 0x40;
+    }
+    bytes
+}
+
+/// An Ion binary `Int`: a magnitude with the sign in the highest bit. Zero
+/// is represented by nothing.
+fn int(negative: bool, magnitude: &[u8]) -> Vec<u8> {
+    let magnitude = trim(magnitude);
+    if magnitude.is_empty() && !negative {
+        return vec![];
+    }
+    let mut bytes = vec![];
+    if !matches!(magnitude.first(), Some(b) if b & 0x80 == 0) {
+        bytes.push(0);
+    }
+    bytes.extend(magnitude);
+    if negative {
+        bytes[0] |= 0x80;
+    }
+    bytes
+}
+
+/// The exponent as a `VarInt`, then the coefficient as an `Int`. Positive
+/// zero with an exponent of zero is represented by nothing.
+fn decimal_representation(elem: &OwnedElement) -> Vec<u8> {
+    let decimal = elem.as_decimal().unwrap();
+    // The scale is the negated exponent, e.g. 2 for `-0.00`.
+    let scale = decimal.scale();
+    let (negative, magnitude) = match BigDecimal::try_from(decimal.clone()) {
+        Ok(decimal) => {
+            let (sign, magnitude) = decimal.as_bigint_and_exponent().0.to_bytes_be();
+            (sign == Sign::Minus, magnitude)
+        }
+        // Negative zero can't be a `BigDecimal`, see `format_element_for_cell`.
+        Err(_) => (true, vec![]),
+    };
+    if scale == 0 && !negative && trim(&magnitude).is_empty() {
+        return vec![];
+    }
+    let mut representation = var_int(scale > 0, scale.unsigned_abs());
+    representation.extend(int(negative, &magnitude));
+    representation
+}
+
+/// The offset in minutes, then the fields up to the timestamp's precision
+/// in UTC.
+fn timestamp_representation(ts: &IonDateTime) -> Vec<u8> {
+    let dt = ts.as_datetime();
+    let mut representation = match ts.offset_kind() {
+        TSOffsetKind::KnownOffset => {
+            let minutes = dt.offset().local_minus_utc() / 60;
+            var_int(minutes < 0, minutes.unsigned_abs() as u64)
+        }
+        TSOffsetKind::UnknownOffset => var_int(true, 0),
+    };
+
+    let utc = dt.naive_utc();
+    let fields = match ts.precision() {
+        TSPrecision::Year => 1,
+        TSPrecision::Month => 2,
+        TSPrecision::Day => 3,
+        TSPrecision::Minute => 5,
+        TSPrecision::Second | TSPrecision::Fractional(_) => 6,
+    };
+    let values = [
+        utc.year() as u64,
+        utc.month() as u64,
+        utc.day() as u64,
+        utc.hour() as u64,
+        utc.minute() as u64,
+        utc.second() as u64,
+    ];
+    for value in &values[..fields] {
+        representation.extend(var_uint(*value));
+    }
+
+    if let TSPrecision::Fractional(mantissa) = ts.precision() {
+        let (digits, coefficient) = match mantissa {
+            Mantissa::Digits(digits) => {
+                let digits = (*digits).min(9);
+                let coefficient = utc.nanosecond() as u64 / 10u64.pow(9 - digits);
+                (digits as u64, coefficient.to_be_bytes().to_vec())
+            }
+            Mantissa::Fraction(fraction) => {
+                let (coefficient, scale) = fraction.as_bigint_and_exponent();
+                (scale.unsigned_abs(), coefficient.to_bytes_be().1)
+            }
+        };
+        representation.extend(var_int(digits > 0, digits));
+        representation.extend(int(false, &coefficient));
+    }
+    representation
+}
+
+fn escape(bytes: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(bytes.len());
+    for b in bytes {
+        if let BEGIN_MARKER | END_MARKER | ESCAPE = *b {
+            escaped.push(ESCAPE);
+        }
+        escaped.push(*b);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ion_rs::types::integer::IntAccess;
+    use ion_rs::value::reader::{element_reader, ElementReader};
+
+    // The test suite only has identity and MD5 expectations, and QLDB
+    // publishes no revision hashes to test against. The SHA-256 hashes below
+    // were computed separately (with Python's hashlib) from the serializations
+    // the test suite specifies, e.g. `0b 80 68 65 6c 6c 6f 0e` for "hello".
+    fn hex(hash: &QldbHash) -> String {
+        hash.bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn ion_string_hashes() {
+        assert_eq!(
+            "2b04b4828341281978fe1e2e82915b797a664ff00b8df7ebf557cdf495c2bfa8",
+            hex(&QldbHash::of_ion_string("hello"))
+        );
+        // Marker bytes in the representation are escaped.
+        assert_eq!(
+            "356df5c42358d3b65e728399f5f471478aa4310b70b94d9f73085e6befec079a",
+            hex(&QldbHash::of_ion_string("a\x0bb"))
+        );
+    }
+
+    #[test]
+    fn dot_is_commutative() {
+        let a = QldbHash::of_ion_string("txid");
+        let b = QldbHash::of_ion_string("select * from foo");
+        assert_eq!(a.dot(&b), b.dot(&a));
+        assert_eq!(
+            "J8PDIWI2Bi7xGO0EOuGguuJRPZZOYl4Qir6HBdmW7I8=",
+            a.dot(&b).to_base64()
+        );
+        assert_eq!(a, a.dot(&QldbHash::default()));
+        assert_eq!(a, QldbHash::default().dot(&a));
+    }
+
+    fn serialized(ion: &str) -> Result<Vec<u8>> {
+        serialize(&element_reader().read_one(ion.as_bytes())?, identity)
+    }
+
+    fn identity(bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+
+    /// The bytes of an s-expression of ints, e.g. `(0x0b 0x20 0x0e)`.
+    fn sexp_bytes(sexp: &OwnedElement) -> Vec<u8> {
+        sexp.as_sequence()
+            .unwrap()
+            .iter()
+            .map(|b| b.as_i64().unwrap() as u8)
+            .collect()
+    }
+
+    /// Runs the Ion Hash test suite (ion-hash-test, published with the Ion
+    /// Hash specification). Its expectations are for an identity hasher, which
+    /// shows exactly what is serialized, so struct fields aren't hashed.
+    #[test]
+    fn ion_hash_test_suite() -> Result<()> {
+        let suite = include_bytes!("../testdata/ion-hash-test/ion_hash_tests.ion");
+        let mut checked = 0;
+        for case in element_reader().read_all(suite)? {
+            let case = case.as_struct().unwrap();
+            let value = match (case.get("ion"), case.get("10n")) {
+                (Some(ion), _) => ion.clone(),
+                (None, Some(binary)) => {
+                    let mut ion = vec![0xe0, 0x01, 0x00, 0xea];
+                    ion.extend(sexp_bytes(binary));
+                    element_reader().read_one(&ion)?
+                }
+                _ => continue,
+            };
+            let expected = case
+                .get("expect")
+                .and_then(|e| e.as_struct())
+                .and_then(|e| e.get("identity"));
+            // The last digest is the serialization of the whole value.
+            let expected = match expected.and_then(|e| e.as_sequence()) {
+                Some(expected) => expected
+                    .iter()
+                    .filter(|e| {
+                        e.annotations()
+                            .any(|a| a.text() == Some("digest") || a.text() == Some("final_digest"))
+                    })
+                    .last()
+                    .map(sexp_bytes),
+                None => None,
+            };
+            if let Some(expected) = expected {
+                assert_eq!(expected, serialize(&value, identity)?, "{:?}", value);
+                checked += 1;
+            }
+        }
+        assert!(checked > 150, "only {} cases were checked", checked);
+        Ok(())
+    }
+
+    #[test]
+    fn negative_zero_decimals() -> Result<()> {
+        // Like `-0d-1` and `-0d-5` in the test suite, the exponent is kept.
+        assert_eq!(vec![0x0B, 0x50, 0xC2, 0x80, 0x0E], serialized("-0.00")?);
+        assert_eq!(vec![0x0B, 0x50, 0x80, 0x80, 0x0E], serialized("-0.")?);
+        assert_eq!(vec![0x0B, 0x50, 0xC2, 0x0E], serialized("0.00")?);
+        Ok(())
+    }
+
+    #[test]
+    fn revision_hashes() -> Result<()> {
+        let value = element_reader().read_one(b"{ a: 1 }")?;
+        assert_eq!(
+            "f5d2d95c18463b4e3b9e5cf7d8e167299e31627c82c15b5e0b822b83ddadc4eb",
+            hex(&QldbHash::of_ion_value(&value)?)
+        );
+
+        let revision = element_reader().read_one(
+            br#"{
+                data: { VIN: "1" },
+                metadata: { id: "abc", version: 0, txTime: 2022-01-31T12:34:56.789Z, txId: "xyz" }
+            }"#,
+        )?;
+        assert_eq!(
+            Some("1da48077e47631348afd4770827312d3ae8f43fe0fa10fc4957ef4808f06e084".to_string()),
+            QldbHash::of_revision(&revision)?.as_ref().map(hex)
+        );
+
+        // Deleted revisions only have metadata.
+        let deleted = element_reader().read_one(
+            br#"{
+                metadata: { id: "abc", version: 0, txTime: 2022-01-31T12:34:56.789Z, txId: "xyz" }
+            }"#,
+        )?;
+        assert_eq!(
+            Some("a86ffb6231b9f94d1c80f43099c73e2128231f1328df8d767d3f1f4e20ee3591".to_string()),
+            QldbHash::of_revision(&deleted)?.as_ref().map(hex)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn proofs() {
+        let revision = QldbHash::of_ion_string("revision");
+        let proof = vec![
+            QldbHash::of_ion_string("sibling"),
+            QldbHash::of_ion_string("uncle"),
+        ];
+        let digest = revision.dot(&proof[0]).dot(&proof[1]);
+        assert_eq!(digest, revision.with_proof(&proof));
+
+        let tampered = QldbHash::of_ion_string("tampered");
+        assert_ne!(digest, tampered.with_proof(&proof));
+        assert_ne!(digest, revision.with_proof(&proof[..1]));
+    }
+}
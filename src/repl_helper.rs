@@ -5,7 +5,7 @@ use rustyline::validate::Validator;
 use rustyline::Context;
 use rustyline::Result as RustylineResult;
 use rustyline::{
-    completion::{Completer, FilenameCompleter, Pair},
+    completion::{Completer, Pair},
     validate::{ValidationContext, ValidationResult},
 };
 use rustyline_derive::Helper;
@@ -14,25 +14,40 @@ use std::{
     fmt::Display,
 };
 
+use crate::completion::{self, Catalog};
+use crate::prompt::Prompt;
 use crate::settings::Environment;
 
 #[derive(Helper)]
 pub(crate) struct QldbHelper {
-    completer: FilenameCompleter,
+    catalog: Catalog,
     highlighter: MatchingBracketHighlighter,
     validator: InputValidator,
     hinter: (),
+    prompt: Prompt,
 }
 
 impl QldbHelper {
     pub fn new(environment: Environment) -> QldbHelper {
         QldbHelper {
-            completer: FilenameCompleter::new(),
+            catalog: Catalog::default(),
             highlighter: MatchingBracketHighlighter::new(),
             validator: InputValidator::new(environment),
             hinter: (),
+            prompt: Prompt::default(),
         }
     }
+
+    /// Called before each readline so that the highlighter knows what state
+    /// the prompt was rendered from.
+    pub fn set_prompt(&mut self, prompt: Prompt) {
+        self.prompt = prompt;
+    }
+
+    /// Called before each readline with the tables and fields known so far.
+    pub fn set_catalog(&mut self, catalog: Catalog) {
+        self.catalog = catalog;
+    }
 }
 
 impl Completer for QldbHelper {
@@ -42,9 +57,17 @@ impl Completer for QldbHelper {
         &self,
         line: &str,
         pos: usize,
-        ctx: &Context<'_>,
+        _ctx: &Context<'_>,
     ) -> Result<(usize, Vec<Pair>), ReadlineError> {
-        self.completer.complete(line, pos, ctx)
+        let (start, candidates) = completion::complete(line, pos, &self.catalog);
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
     }
 }
 
@@ -57,22 +80,33 @@ impl Hinter for QldbHelper {
 }
 
 impl Highlighter for QldbHelper {
-    /// Use the default for prompts like history search, else use a bold + color code. We use blue for 'not in a tx' and green for 'in a tx'. Hopefully this is color blind friendly.
+    /// Use the default for prompts like history search, else use a bold + color code. We use blue for 'not in a tx' and green for 'in a tx' (yellow/red as the tx times out). Hopefully this is color blind friendly.
+    ///
+    /// If the prompt template contained its own style markup, that wins.
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
         default: bool,
     ) -> Cow<'b, str> {
-        if default {
-            // FIXME: Use ansi crate
-            // FIXME: Find another way of determining state (than substring matching)
-            if prompt.contains("*") {
-                Owned(format!("\x1b[1;32m{}\x1b[0m", prompt))
-            } else {
-                Owned(format!("\x1b[1;34m{}\x1b[0m", prompt))
+        if !default {
+            return Borrowed(prompt);
+        }
+
+        if prompt == self.prompt.text {
+            if let Some(ref styled) = self.prompt.styled {
+                return Borrowed(&styled[..]);
             }
-        } else {
-            Borrowed(prompt)
+        }
+
+        // FIXME: Use ansi crate
+        //
+        // Transactions turn yellow as they approach QLDB's timeout, and red once
+        // they have expired.
+        match self.prompt.state.transaction {
+            Some(ref tx) if tx.is_expired() => Owned(format!("\x1b[1;31m{}\x1b[0m", prompt)),
+            Some(ref tx) if tx.is_expiring() => Owned(format!("\x1b[1;33m{}\x1b[0m", prompt)),
+            Some(_) => Owned(format!("\x1b[1;32m{}\x1b[0m", prompt)),
+            None => Owned(format!("\x1b[1;34m{}\x1b[0m", prompt)),
         }
     }
 
@@ -1,13 +1,48 @@
 use crate::{settings::FormatMode, ui::Ui};
 use amazon_qldb_driver::{ion_compat, StatementResults};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ion_c_sys::reader::IonCReaderHandle;
 use ion_c_sys::result::IonCError;
+use ion_rs::value::{
+    owned::OwnedElement,
+    reader::{element_reader, ElementReader},
+    writer::{Format, TextKind},
+};
 use itertools::Itertools;
 use table::display_results_table;
 use tracing::warn;
 
 mod table;
+mod writer;
+
+pub(crate) use table::format_element_for_cell;
+pub use writer::ExportFormat;
+pub(crate) use writer::{
+    ion_literal, ion_struct, ion_text, json_string, write_ion, DocumentWriter,
+};
+
+/// Parses every document in `results`.
+pub(crate) fn elements(results: &StatementResults) -> Result<Vec<OwnedElement>> {
+    parse_values(results.raw_values())
+}
+
+/// Parses documents kept as binary Ion, one per value.
+pub(crate) fn parse_values<I>(values: I) -> Result<Vec<OwnedElement>>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    let element_reader = element_reader();
+
+    values
+        .map(
+            |data| match element_reader.iterate_over(data.as_ref())?.next() {
+                None => Err(anyhow!("found no value, which is unexpected"))?,
+                Some(r) => Ok(r?),
+            },
+        )
+        .collect()
+}
 
 pub(crate) fn display_results(results: &StatementResults, format: &FormatMode, ui: &Box<dyn Ui>) {
     match format {
@@ -20,6 +55,14 @@ pub(crate) fn display_results(results: &StatementResults, format: &FormatMode, u
     }
 }
 
+/// Formats a single value the way the `ion` format mode formats results.
+pub(crate) fn pretty_ion_text(elem: &OwnedElement) -> Result<String> {
+    Ok(String::from_utf8(write_ion(
+        elem,
+        Format::Text(TextKind::Pretty),
+    )?)?)
+}
+
 fn display_results_ion_text(results: &StatementResults, ui: &Box<dyn Ui>) {
     let iter = results.readers().map(|r| ion_text_string(r));
     Itertools::intersperse(iter, ",\n".to_owned()).for_each(|p| ui.print(&p));
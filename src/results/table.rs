@@ -1,28 +1,17 @@
 use crate::ui::Ui;
 use amazon_qldb_driver::StatementResults;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use comfy_table::Table;
 use ion_c_sys::timestamp::IonDateTime;
 use ion_rs::external::bigdecimal::BigDecimal;
+use ion_rs::value::owned::OwnedElement;
 use ion_rs::value::*;
-use ion_rs::value::{
-    owned::OwnedElement,
-    reader::{element_reader, ElementReader},
-};
 use ion_rs::{Integer, IonType};
 use std::convert::TryFrom;
 use std::{collections::HashSet, convert::TryInto};
 
 pub(crate) fn display_results_table(results: &StatementResults, ui: &Box<dyn Ui>) -> Result<()> {
-    let element_reader = element_reader();
-
-    let elems: Vec<_> = results
-        .raw_values()
-        .map(|data| match element_reader.iterate_over(data)?.next() {
-            None => Err(anyhow!("found no value, which is unexpected"))?,
-            Some(r) => Ok(r?),
-        })
-        .collect::<Result<_>>()?;
+    let elems = super::elements(results)?;
 
     let refs: Vec<_> = elems.iter().map(|e| e).collect();
     let table = format_table(&refs[..])?;
@@ -105,7 +94,7 @@ fn build_table(elems: &[&OwnedElement]) -> Result<Table> {
     Ok(table)
 }
 
-fn format_element_for_cell(elem: Option<&OwnedElement>) -> Result<String> {
+pub(crate) fn format_element_for_cell(elem: Option<&OwnedElement>) -> Result<String> {
     let elem = match elem {
         None => return Ok("".to_string()),
         Some(e) => e,
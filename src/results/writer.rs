@@ -0,0 +1,448 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use ion_rs::value::owned::{text_token, OwnedElement, OwnedStruct};
+use ion_rs::value::writer::{ElementWriter, Format, TextKind};
+use ion_rs::value::{Element, Sequence, Struct, SymbolToken};
+use ion_rs::{Integer, IonType};
+
+use super::format_element_for_cell;
+use crate::error::{usage_error, ShellError};
+
+/// The formats `\export` writes and `\import` reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ion,
+    IonBinary,
+    Jsonl,
+    Csv,
+}
+
+impl ExportFormat {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Ion => "ion",
+            ExportFormat::IonBinary => "ion-binary",
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ShellError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match &s.to_lowercase()[..] {
+            "ion" | "ion-text" => ExportFormat::Ion,
+            "ion-binary" => ExportFormat::IonBinary,
+            "jsonl" | "json" => ExportFormat::Jsonl,
+            "csv" => ExportFormat::Csv,
+            _ => return Err(usage_error(format!("{} is not a valid export format", s))),
+        })
+    }
+}
+
+/// Writes documents one page at a time.
+pub(crate) struct DocumentWriter<W: Write> {
+    format: ExportFormat,
+    out: W,
+    /// The CSV columns. They are taken from the first page, as the header
+    /// has to be written before anything else.
+    columns: Option<Vec<String>>,
+}
+
+impl<W: Write> DocumentWriter<W> {
+    /// `columns` are the CSV columns of an export that is being resumed.
+    pub(crate) fn new(format: ExportFormat, out: W, columns: Option<Vec<String>>) -> Self {
+        DocumentWriter {
+            format,
+            out,
+            columns,
+        }
+    }
+
+    pub(crate) fn columns(&self) -> Option<&[String]> {
+        self.columns.as_deref()
+    }
+
+    /// Writes `elems`. Nothing is written if any of them can't be, so that
+    /// an export can stop at the page before.
+    pub(crate) fn write_page(&mut self, elems: &[OwnedElement]) -> Result<()> {
+        let mut page = vec![];
+        match self.format {
+            // Every value is a complete Ion binary stream, so they can simply
+            // be concatenated.
+            ExportFormat::IonBinary => {
+                for elem in elems {
+                    page.extend(write_ion(elem, Format::Binary)?);
+                }
+            }
+            ExportFormat::Ion => {
+                for elem in elems {
+                    page.extend(write_ion(elem, Format::Text(TextKind::Pretty))?);
+                    page.push(b'\n');
+                }
+            }
+            ExportFormat::Jsonl => {
+                for elem in elems {
+                    writeln!(page, "{}", to_json(elem)?)?;
+                }
+            }
+            ExportFormat::Csv => {
+                let columns = match self.columns {
+                    Some(ref columns) => columns.clone(),
+                    None => {
+                        let columns = csv_columns(elems);
+                        let header: Vec<_> = columns.iter().map(|c| csv_quote(c)).collect();
+                        writeln!(page, "{}", header.join(","))?;
+                        columns
+                    }
+                };
+                for elem in elems {
+                    let (row, missing) = csv_row(elem, &columns)?;
+                    if let Some(field) = missing.first() {
+                        Err(usage_error(format!(
+                            "{} is not one of the CSV columns, which are taken from the first page ({}), export as ion or jsonl instead",
+                            field,
+                            columns.join(", ")
+                        )))?;
+                    }
+                    writeln!(page, "{}", row)?;
+                }
+                self.columns = Some(columns);
+            }
+        }
+        self.out.write_all(&page)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// The largest value [`write_ion`] writes. QLDB documents are at most 128KB,
+/// but Ion text can be several times larger than the binary.
+const MAX_ION_BYTES: usize = 16 * 1024 * 1024;
+
+/// Writes `elem` with Ion C, as text or binary. Ion C writes into a buffer of
+/// a fixed size, so the buffer grows until the value fits.
+pub(crate) fn write_ion(elem: &OwnedElement, format: Format) -> Result<Vec<u8>> {
+    let mut size = 4 * 1024;
+    loop {
+        let mut buf = vec![0; size];
+        let written = format
+            .element_writer_for_slice(&mut buf)
+            .and_then(|mut writer| {
+                writer.write(elem)?;
+                Ok(writer.finish()?.len())
+            });
+        match written {
+            Ok(len) => {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            Err(e) if size >= MAX_ION_BYTES => {
+                return Err(anyhow!("unable to write a value as Ion: {}", e))
+            }
+            Err(_) => size *= 4,
+        }
+    }
+}
+
+/// Every field of `elems`, in the order they are first seen (like the table
+/// format). Values that aren't structs go in a `VALUE` column.
+fn csv_columns(elems: &[OwnedElement]) -> Vec<String> {
+    let mut columns = vec![];
+    let mut single_value = false;
+    for elem in elems {
+        match elem.as_struct() {
+            Some(strukt) if !elem.is_null() => {
+                for (name, _) in strukt.iter() {
+                    let name = name.text().unwrap_or_default().to_string();
+                    if !columns.contains(&name) {
+                        columns.push(name);
+                    }
+                }
+            }
+            _ => single_value = true,
+        }
+    }
+    if single_value {
+        columns.insert(0, "VALUE".to_string());
+    }
+    columns
+}
+
+fn csv_row(elem: &OwnedElement, columns: &[String]) -> Result<(String, Vec<String>)> {
+    let strukt = match elem.as_struct() {
+        Some(strukt) if !elem.is_null() => strukt,
+        _ => {
+            let mut cells = vec![String::new(); columns.len()];
+            return Ok(match columns.iter().position(|c| c == "VALUE") {
+                Some(i) => {
+                    cells[i] = csv_quote(&csv_cell(elem)?);
+                    (cells.join(","), vec![])
+                }
+                None => (cells.join(","), vec!["VALUE".to_string()]),
+            });
+        }
+    };
+
+    let mut cells = vec![];
+    for column in columns {
+        cells.push(match strukt.get(column) {
+            Some(value) => csv_quote(&csv_cell(value)?),
+            None => String::new(),
+        });
+    }
+    let missing = strukt
+        .iter()
+        .filter_map(|(name, _)| name.text())
+        .filter(|name| !columns.iter().any(|c| c == name))
+        .map(|name| name.to_string())
+        .collect();
+    Ok((cells.join(","), missing))
+}
+
+/// Scalars are formatted like table cells. Nested values are written as JSON,
+/// rather than as nested tables.
+fn csv_cell(elem: &OwnedElement) -> Result<String> {
+    match elem.ion_type() {
+        IonType::List | IonType::SExpression | IonType::Struct if !elem.is_null() => to_json(elem),
+        _ => format_element_for_cell(Some(elem)),
+    }
+}
+
+fn csv_quote(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Converts `elem` to JSON, the way the Ion specification suggests: types
+/// JSON doesn't have become strings (timestamps, blobs as base64) or
+/// numbers (decimals), and annotations are dropped.
+pub(crate) fn to_json(elem: &OwnedElement) -> Result<String> {
+    if elem.is_null() {
+        return Ok("null".to_string());
+    }
+    Ok(match elem.ion_type() {
+        IonType::Null => "null".to_string(),
+        IonType::Boolean => elem.as_bool().unwrap().to_string(),
+        IonType::Integer => match elem.as_integer().unwrap() {
+            Integer::I64(i) => i.to_string(),
+            Integer::BigInt(i) => i.to_string(),
+        },
+        IonType::Float => {
+            let f = elem.as_f64().unwrap();
+            if f.is_finite() {
+                format!("{:?}", f)
+            } else {
+                "null".to_string()
+            }
+        }
+        IonType::Decimal => format_element_for_cell(Some(elem))?,
+        IonType::Timestamp => json_string(&format_element_for_cell(Some(elem))?),
+        IonType::Symbol => json_string(elem.as_sym().unwrap().text().unwrap_or_default()),
+        IonType::String => json_string(elem.as_str().unwrap()),
+        IonType::Clob | IonType::Blob => json_string(&base64::encode(elem.as_bytes().unwrap())),
+        IonType::List | IonType::SExpression => {
+            let items = elem
+                .as_sequence()
+                .unwrap()
+                .iter()
+                .map(to_json)
+                .collect::<Result<Vec<_>>>()?;
+            format!("[{}]", items.join(","))
+        }
+        IonType::Struct => {
+            let mut fields = vec![];
+            for (name, value) in elem.as_struct().unwrap().iter() {
+                let name = json_string(name.text().unwrap_or_default());
+                fields.push(format!("{}:{}", name, to_json(value)?));
+            }
+            format!("{{{}}}", fields.join(","))
+        }
+    })
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes `elem` as compact Ion text, on one line.
+pub(crate) fn ion_text(elem: &OwnedElement) -> Result<String> {
+    Ok(String::from_utf8(write_ion(
+        elem,
+        Format::Text(TextKind::Compact),
+    )?)?)
+}
+
+/// Writes `elem` as Ion text that can be embedded in a statement (between
+/// backticks). Backticks are escaped, so they can't end the literal.
+pub(crate) fn ion_literal(elem: &OwnedElement) -> Result<String> {
+    Ok(escape_backticks(&ion_text(elem)?))
+}
+
+/// Builds a struct, e.g. to write with [`ion_text`].
+pub(crate) fn ion_struct<'a, I>(fields: I) -> OwnedElement
+where
+    I: IntoIterator<Item = (&'a str, OwnedElement)>,
+{
+    fields
+        .into_iter()
+        .map(|(name, value)| (text_token(name), value))
+        .collect::<OwnedStruct>()
+        .into()
+}
+
+/// Backticks can only be in quoted text (strings, symbols and clobs), where
+/// they are escaped, or in operator symbols (in s-expressions), which are
+/// quoted so that they can be escaped.
+fn escape_backticks(text: &str) -> String {
+    const OPERATORS: &str = "!#%&*+-./;<=>?@^`|~";
+    let mut escaped = String::with_capacity(text.len());
+    let mut quote = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(_) if c == '\\' => {
+                escaped.push(c);
+                escaped.extend(chars.next());
+                continue;
+            }
+            Some(_) if c == '`' => {
+                escaped.push_str("\\x60");
+                continue;
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if OPERATORS.contains(c) => {
+                let mut operator = c.to_string();
+                while let Some(&next) = chars.peek().filter(|&&next| OPERATORS.contains(next)) {
+                    operator.push(next);
+                    chars.next();
+                }
+                match operator.contains('`') {
+                    true => escaped.push_str(&format!("'{}'", operator.replace('`', "\\x60"))),
+                    false => escaped.push_str(&operator),
+                }
+                continue;
+            }
+            None => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ion_rs::value::reader::{element_reader, ElementReader};
+
+    fn elements(ion: &str) -> Result<Vec<OwnedElement>> {
+        Ok(element_reader().read_all(ion.as_bytes())?)
+    }
+
+    #[test]
+    fn json() -> Result<()> {
+        let elem = &elements(
+            r#"{ name: "a \"b\"", tags: [x, 1, 2.50, 1e0], at: null.timestamp, data: {{AAEC}} }"#,
+        )?[0];
+        // Struct fields aren't kept in order, so the JSON is compared as Ion.
+        assert_eq!(
+            elements(r#"{"name":"a \"b\"","tags":["x",1,2.50,1.0],"at":null,"data":"AAEC"}"#)?,
+            elements(&to_json(elem)?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn csv() -> Result<()> {
+        let elems = elements(r#"{ a: 1, b: "x,y" } { a: 2, c: [1] }"#)?;
+        let mut columns = csv_columns(&elems);
+        columns.sort();
+        assert_eq!(vec!["a", "b", "c"], columns);
+        assert_eq!(
+            ("1,\"x,y\",".to_string(), vec![]),
+            csv_row(&elems[0], &columns)?
+        );
+        assert_eq!(
+            ("2,,[1]".to_string(), vec![]),
+            csv_row(&elems[1], &columns)?
+        );
+
+        let (row, missing) = csv_row(&elements("{ a: 3, d: true }")?[0], &columns)?;
+        assert_eq!("3,,", row);
+        assert_eq!(vec!["d"], missing);
+        assert_eq!(
+            (",,".to_string(), vec!["VALUE".to_string()]),
+            csv_row(&elements("4")?[0], &columns)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_pages() -> Result<()> {
+        let page = elements(r#"{ a: 1 } { a: "x`y" }"#)?;
+        for format in [ExportFormat::Ion, ExportFormat::IonBinary] {
+            let mut writer = DocumentWriter::new(format, vec![], None);
+            writer.write_page(&page)?;
+            writer.write_page(&page[..1])?;
+            assert_eq!(
+                vec![page[0].clone(), page[1].clone(), page[0].clone()],
+                element_reader().read_all(&writer.out)?
+            );
+        }
+
+        let mut writer = DocumentWriter::new(ExportFormat::Csv, vec![], None);
+        writer.write_page(&page)?;
+        assert!(writer.write_page(&elements("{ a: 2, b: 3 }")?).is_err());
+        assert_eq!("a\n1\nx`y\n", String::from_utf8(writer.out)?);
+        Ok(())
+    }
+
+    #[test]
+    fn ion_literals() -> Result<()> {
+        let ion = r#"t::{ 'a b': "x`y\n", sym: 'q\'', n: null.int, d: 1.50, e: 1d3,
+            f: 2.5e0, ts: 2022-01-31T10:00:00.500-00:00, day: 2022-01-31T,
+            blob: {{AAEC}}, clob: {{"hi"}}, s: (a 1), l: [true, -7] }"#;
+        let elem = &elements(ion)?[0];
+        let literal = ion_literal(elem)?;
+        assert!(!literal.contains('`'));
+        assert_eq!(elem, &elements(&literal)?[0]);
+
+        assert_eq!(r#""a\x60b""#, escape_backticks(r#""a`b""#));
+        assert_eq!(r#"'\\'"#, escape_backticks(r#"'\\'"#));
+        assert_eq!(r#"(a '\x60+' - b)"#, escape_backticks("(a `+ - b)"));
+        let sexp = &elements(r#"('`' '<`>' "`")"#)?[0];
+        assert_eq!(sexp, &elements(&ion_literal(sexp)?)?[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn export_formats() -> Result<()> {
+        assert_eq!(ExportFormat::IonBinary, "ion-binary".parse()?);
+        assert_eq!(ExportFormat::Csv, "CSV".parse()?);
+        assert!("xml".parse::<ExportFormat>().is_err());
+        Ok(())
+    }
+}
@@ -2,13 +2,19 @@ use amazon_qldb_driver::QldbSession;
 use anyhow::Result;
 use core::fmt;
 use ion_c_sys::reader::IonCReader;
+use ion_rs::value::owned::OwnedElement;
 use rustyline::error::ReadlineError;
 use tracing::{instrument, span, trace, Instrument, Level};
 
+use crate::batch::BatchProgress;
+use crate::completion::Catalog;
+use crate::diff::RecentResults;
+use crate::prompt::{self, LastStatus, PromptState};
 use crate::transaction::ShellTransaction;
 use crate::{
     command::{self, UseCommand},
-    settings::Environment,
+    journal, proof, results,
+    settings::{Environment, ProofCommand, Subcommand},
 };
 use crate::{Deps, QldbShellError};
 
@@ -29,6 +35,8 @@ pub(crate) const HELP_TEXT: &'static str = r#"Shell Keys
   Escape+Enter (macOS, *nix)
   Shift+Enter (Windows)
     - Starts a new line to enter a statement that spans multiple lines. You can also copy input text with multiple lines and paste it into the shell.
+  Tab
+    - Completes keywords, functions, table names (after FROM, INTO and UPDATE) and field names seen in earlier results. After a backslash, completes commands, their flags and values.
   Ctrl+C
     - Cancels the current command.
   Ctrl+D
@@ -40,6 +48,8 @@ Database commands
     - Manually starts a transaction. You can run multiple statements within a transaction interactively, or non-interactively by batching commands and statements sequentially. 
     Transactions that are not committed within 30 seconds will time out, and QLDB will reject any changes made during the transaction. 
     For more details and examples, see the QLDB Developer Guide [https://docs.aws.amazon.com/qldb/latest/developerguide/data-shell.html#data-shell-transactions].
+  begin as NAME
+    - Starts a named transaction. Any open transaction stays open, see \tx use.
   commit
     - Commits a transaction. If there is no transaction in progress, the shell reports an error saying that there is no active transaction.
   abort
@@ -53,12 +63,68 @@ Database commands
 Shell Meta Commands
   \use -l LEDGER_NAME [-p PROFILE] [-r REGION_CODE] [-s QLDB_SESSION_ENDPOINT]
     - Switch to a different ledger (or: region, endpoint, AWS profile) without restarting the shell.
+  \set
+    - Prints the current value of every setting below.
   \set edit-mode [emacs|vi]
     - Toggle between Emacs/Vi keybindings.
   \set terminator-required [true|false] 
     - Toggle if a line terminator is required to end each statement.
+  \set format [ion|table]
+    - Change the output format of query results.
+  \set auto-commit [true|false]
+    - Toggle if statements outside of a transaction are run in their own transaction.
+  \set display-query-metrics [true|false]
+    - Toggle if metrics are printed after the results of a query.
+  \set display-ctrl-signals [true|false]
+    - Toggle if CTRL-C and CTRL-D are echoed.
+  \set verify-hashes [true|false]
+    - Toggle if the hash of every revision a query returns is checked, see \hash.
+  \set prompt ["PROMPT"]
+    - Change the prompt. Without a value, the default prompt is restored.
+  \dryrun [on|off]
+    - Toggle dry run mode: statements run as usual, but every transaction is rolled back instead of committed, and ledgers are not changed.
+  \tx [log]
+    - Lists every statement run in the current transaction, with its document count and metrics.
+  \tx list
+    - Lists every open transaction.
+  \tx use NAME
+    - Switches to another open transaction. Statements, commit and abort apply to the active transaction.
+  \retry
+    - Replays the statements that succeeded in the last transaction that failed to commit because of an OCC conflict in a new transaction, and commits it.
   \show tables
     - Display a list of active tables in the current ledger.
+  \show ledgers
+    - Display a list of the ledgers in the current region. The current ledger is marked with *.
+  \show indexes [TABLE]
+    - Display the indexes of a table (or of every active table), with their status and any build errors.
+  \history-of TABLE DOCUMENT_ID [--from TIMESTAMP] [--to TIMESTAMP]
+    - Show every revision of a document with its version, txTime and txId, as a diff against the previous revision.
+  \diff revisions TABLE DOCUMENT_ID OLD_VERSION NEW_VERSION
+    - Show the paths that were added, removed or changed between two revisions of a document.
+  \diff documents TABLE OLD_ID NEW_ID
+    - Show the paths that differ between two documents.
+  \diff results
+    - Show the differences between the documents returned by the last two statements. Documents are matched by their id (metadata.id or documentId) if they all have one, and compared by position otherwise.
+  \verify TABLE DOCUMENT_ID
+    - Verify the latest revision of a document against the current digest of the ledger, using a proof from QLDB. The revision's hash is recomputed from its data and metadata first.
+  \hash
+    - Recompute the hash of every revision returned by the last statement (e.g. from _ql_committed_TABLE or history()) and compare it with the hash QLDB stored.
+  \export TABLE|QUERY PATH [--format ion|ion-binary|jsonl|csv]
+    - Write a table or the results of a query to a file, a page at a time. Running an interrupted table export again resumes it; query exports start over, as their results may have changed.
+  \import PATH into TABLE [--format ion|ion-binary|jsonl|csv] [--type FIELD=TYPE]... [--batch N] [--skip N] [--dry-run]
+    - Insert the documents in a file into a table, in batches of up to 40 documents per transaction.
+  \describe TABLE [--sample N]
+    - Display the id, status and indexes of a table. With --sample, also summarizes the fields of the first N documents (only the pages they are on are read).
+  \ledger create NAME [--permissions-mode ALLOW_ALL|STANDARD] [--deletion-protection] [--tag KEY=VALUE]... [--wait]
+    - Create a ledger. With --wait, waits until the ledger is active.
+  \ledger describe [NAME]
+    - Describe a ledger (by default, the current one).
+  \ledger update [NAME] [--permissions-mode ALLOW_ALL|STANDARD] [--deletion-protection true|false]
+    - Change the permissions mode or deletion protection of a ledger.
+  \ledger delete NAME [--yes]
+    - Delete a ledger, after typing its name again (or with --yes). Deletion protection has to be turned off first.
+  \ledger tags [NAME] [--add KEY=VALUE]... [--remove KEY]...
+    - List, add or remove the tags of a ledger.
   \status
     - Prints out your current region, ledger and Shell version.
   \env
@@ -71,6 +137,18 @@ where
 {
     pub(crate) deps: Deps<C>,
     pub(crate) current_transaction: Option<ShellTransaction>,
+    pub(crate) last_status: LastStatus,
+    /// The statements of the last transaction that failed to commit because
+    /// of an OCC conflict, see `\retry`.
+    pub(crate) conflicted_statements: Option<Vec<String>>,
+    pub(crate) batch_progress: BatchProgress,
+    /// Transactions started with `begin as NAME` that aren't the active one.
+    pub(crate) parked_transactions: Vec<ShellTransaction>,
+    /// The documents returned by the last two statements, oldest first, see
+    /// `\diff results`.
+    pub(crate) recent_results: Vec<RecentResults>,
+    /// Tables and fields, for tab completion.
+    pub(crate) catalog: Catalog,
 }
 
 impl<C> fmt::Debug for Runner<C>
@@ -85,28 +163,66 @@ where
 fn is_special_command(line: &str) -> bool {
     match &line.to_lowercase()[..] {
         "help" | "quit" | "exit" | "start transaction" | "begin" | "abort" | "commit" => true,
-        _ => false,
+        _ => transaction_name(line).is_some(),
     }
 }
 
-fn build_prompt(env: &Environment, transaction_active: bool) -> String {
-    let prompt = match env.config().ui.prompt {
-        Some(ref p) => p.clone(),
-        _ => "qldb$ACTIVE_TRANSACTION> ".to_string(),
-    };
-    let current_region = env.current_region();
+/// Commands that take no arguments, handled before the line is parsed as a
+/// `Backslash`. Tab completion offers them too.
+pub(crate) enum SimpleCommand {
+    Help,
+    Quit,
+    Begin,
+    Abort,
+    Commit,
+    Env,
+    ShowSettings,
+    Retry,
+    TxLog,
+    Status,
+}
+
+pub(crate) const SIMPLE_COMMANDS: &[(&str, SimpleCommand)] = &[
+    ("help", SimpleCommand::Help),
+    ("?", SimpleCommand::Help),
+    ("quit", SimpleCommand::Quit),
+    ("exit", SimpleCommand::Quit),
+    ("start transaction", SimpleCommand::Begin),
+    ("begin", SimpleCommand::Begin),
+    ("abort", SimpleCommand::Abort),
+    ("commit", SimpleCommand::Commit),
+    ("env", SimpleCommand::Env),
+    ("set", SimpleCommand::ShowSettings),
+    ("retry", SimpleCommand::Retry),
+    ("tx", SimpleCommand::TxLog),
+    ("status", SimpleCommand::Status),
+];
+
+/// Parses `begin as NAME` (or `start transaction as NAME`).
+fn transaction_name(line: &str) -> Option<&str> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    let (name, keywords) = words.split_last()?;
+    match &keywords.join(" ").to_lowercase()[..] {
+        "begin as" | "start transaction as" => Some(*name),
+        _ => None,
+    }
+}
+
+fn prompt_state(
+    env: &Environment,
+    transaction: Option<&ShellTransaction>,
+    last_status: LastStatus,
+) -> PromptState {
     let current_ledger = env.current_ledger();
 
-    prompt
-        .replace("$REGION", current_region.as_ref())
-        .replace("$LEDGER", &current_ledger.name[..])
-        .replace(
-            "$ACTIVE_TRANSACTION",
-            match transaction_active {
-                true => " *",
-                false => "",
-            },
-        )
+    PromptState {
+        region: env.current_region().as_ref().to_string(),
+        ledger: current_ledger.name.clone(),
+        profile: current_ledger.profile.clone(),
+        endpoint: current_ledger.qldb_session_endpoint.clone(),
+        transaction: transaction.map(|tx| tx.state()),
+        last_status,
+    }
 }
 
 impl<C> Runner<C>
@@ -114,29 +230,91 @@ where
     C: QldbSession + Send + Sync + Clone + 'static,
 {
     pub(crate) async fn start(&mut self) -> Result<ProgramFlow> {
+        // Scripts don't need completion, so they don't pay for the query.
+        if atty::is(atty::Stream::Stdin) {
+            self.refresh_catalog().await;
+        }
         loop {
             let span = span!(Level::TRACE, "tick");
             match self.tick().instrument(span).await {
-                Ok(TickFlow::Again) => {}
+                Ok(TickFlow::Again) => self.last_status = LastStatus::Ok,
                 Ok(TickFlow::Exit) => return Ok(ProgramFlow::Exit),
                 Ok(TickFlow::Restart) => return Ok(ProgramFlow::Restart),
-                Err(e) => self.deps.ui.eprintln(&format!("{}", e)),
+                Err(e) => {
+                    self.last_status = LastStatus::Error;
+                    // A failed batch means the rest of the script can't be
+                    // trusted to run, so we stop (and exit non-zero).
+                    if let Some(QldbShellError::BatchFailed(_)) = e.downcast_ref() {
+                        return Err(e);
+                    }
+                    self.deps.ui.eprintln(&format!("{}", e))
+                }
+            }
+        }
+    }
+
+    /// Runs a command given on the command line (e.g. `qldb proof export`)
+    /// instead of the shell.
+    pub(crate) async fn run_subcommand(&mut self, command: Subcommand) -> Result<()> {
+        match command {
+            Subcommand::Proof(ProofCommand::Export {
+                table,
+                document_id,
+                bundle,
+            }) => self.export_proof(&table, &document_id, &bundle).await,
+            Subcommand::Proof(ProofCommand::Verify {
+                bundle,
+                digest,
+                digest_tip,
+            }) => {
+                let trusted = proof::TrustedDigest {
+                    digest,
+                    tip_address: digest_tip,
+                };
+                proof::verify_bundle_file(&bundle, &trusted)
             }
+            Subcommand::Journal(journal) => journal::query_journal(&journal),
         }
     }
 
     #[instrument]
     pub(crate) async fn tick(&mut self) -> Result<TickFlow> {
-        self.deps.ui.set_prompt(build_prompt(
+        self.warn_if_transaction_expiring();
+        let state = prompt_state(
             &self.deps.env,
-            self.current_transaction.is_some(),
-        ));
+            self.current_transaction.as_ref(),
+            self.last_status,
+        );
+        let template = self.deps.env.config().ui.prompt.clone();
+        self.deps
+            .ui
+            .set_prompt(prompt::build_prompt(template.as_deref(), state));
 
         let user_input = self.deps.ui.user_input();
         Ok(match user_input {
             Ok(line) => {
                 trace!(line = &line[..], "user input");
 
+                let in_batch = self.in_batch();
+                if let Some(expired) = self.take_expired_transaction() {
+                    // The rest of a script would run in a new batch, which
+                    // would split it into several commits.
+                    if in_batch {
+                        return Err(self.fail_batch(expired.into()).await.into());
+                    }
+                    // Commands that don't need the transaction can carry on.
+                    match &line.to_lowercase()[..] {
+                        "" | "abort" => {
+                            self.deps.ui.eprintln(&format!("{}", expired));
+                            return Ok(TickFlow::Again);
+                        }
+                        l if l.starts_with(r"\") || l == "help" || l == "quit" || l == "exit" => {
+                            self.deps.ui.eprintln(&format!("{}", expired))
+                        }
+                        _ => Err(expired)?,
+                    }
+                }
+
                 if line.is_empty() {
                     TickFlow::Again
                 } else {
@@ -144,8 +322,8 @@ where
                         r"\" => self.handle_command(&line[1..]).await?,
                         _ if is_special_command(&line) => self.handle_command(&line).await?,
                         _ => match self.current_transaction {
-                            Some(_) => self.handle_partiql(&line).await?,
-                            None => self.handle_autocommit_partiql(&line).await?,
+                            Some(_) if !self.in_batch() => self.handle_partiql(&line).await?,
+                            _ => self.handle_autocommit_partiql(&line).await?,
                         },
                     }
                 }
@@ -170,7 +348,11 @@ where
         if self.deps.env.config().ui.display_ctrl_signals {
             self.deps.ui.println("CTRL-D");
         }
-        Ok(if let Some(_) = self.current_transaction {
+        Ok(if self.in_batch() {
+            // The end of a script, commit whatever is left.
+            self.commit_batch().await?;
+            TickFlow::Exit
+        } else if self.current_transaction.is_some() {
             self.handle_abort().await?;
             TickFlow::Again
         } else {
@@ -179,28 +361,39 @@ where
     }
 
     pub(crate) async fn handle_command(&mut self, line: &str) -> Result<TickFlow> {
-        match &line.to_lowercase()[..] {
-            "help" | "?" => {
+        let lowercase = line.to_lowercase();
+        let command = SIMPLE_COMMANDS
+            .iter()
+            .find(|(name, _)| *name == lowercase)
+            .map(|(_, command)| command);
+        match command {
+            Some(SimpleCommand::Help) => {
                 self.deps.ui.println(HELP_TEXT);
             }
-            "quit" | "exit" => {
+            Some(SimpleCommand::Quit) => {
                 return Ok(TickFlow::Exit);
             }
-            "start transaction" | "begin" => self.handle_start_transaction()?,
-            "abort" => self.handle_abort().await?,
-            "commit" => self.handle_commit().await?,
-            "env" => self.handle_env(),
-            "show tables" => self.handle_show_tables().await?,
-            "status" => self.handle_status().await?,
-            _ => return self.handle_complex_command(line).await,
+            Some(SimpleCommand::Begin) => self.handle_start_transaction(None).await?,
+            None if transaction_name(line).is_some() => {
+                self.handle_start_transaction(transaction_name(line))
+                    .await?
+            }
+            Some(SimpleCommand::Abort) => self.handle_abort().await?,
+            Some(SimpleCommand::Commit) => self.handle_commit().await?,
+            Some(SimpleCommand::Env) => self.handle_env(),
+            Some(SimpleCommand::ShowSettings) => self.handle_show_settings(),
+            Some(SimpleCommand::Retry) => self.handle_retry().await?,
+            Some(SimpleCommand::TxLog) => self.handle_tx_log()?,
+            Some(SimpleCommand::Status) => self.handle_status().await?,
+            None => return self.handle_complex_command(line).await,
         }
 
         Ok(TickFlow::Again)
     }
 
     pub(crate) async fn handle_complex_command(&mut self, line: &str) -> Result<TickFlow> {
-        let iter = line.split_ascii_whitespace();
-        let backslash = match command::backslash(iter) {
+        let args = command::split_args(line);
+        let backslash = match command::backslash(args) {
             Ok(b) => b,
             Err(_) => Err(QldbShellError::UnknownCommand)?,
         };
@@ -215,6 +408,24 @@ where
                         command::SetCommand::TerminatorRequired(ref tf) => {
                             env.config.ui.terminator_required = tf.into();
                         }
+                        command::SetCommand::Format(ref format) => {
+                            env.config.ui.format = format.clone();
+                        }
+                        command::SetCommand::AutoCommit(ref tf) => {
+                            env.config.ui.auto_commit = tf.into();
+                        }
+                        command::SetCommand::DisplayQueryMetrics(ref tf) => {
+                            env.config.ui.display_query_metrics = tf.into();
+                        }
+                        command::SetCommand::DisplayCtrlSignals(ref tf) => {
+                            env.config.ui.display_ctrl_signals = tf.into();
+                        }
+                        command::SetCommand::VerifyHashes(ref tf) => {
+                            env.config.ui.verify_hashes = tf.into();
+                        }
+                        command::SetCommand::Prompt { ref template } => {
+                            env.config.ui.prompt = template.clone();
+                        }
                     };
                     Ok(())
                 })?;
@@ -223,6 +434,87 @@ where
                 Ok(TickFlow::Again)
             }
             command::Backslash::Use(u) => self.handle_use_command(u).await,
+            command::Backslash::Show(command::ShowCommand::Tables) => {
+                self.handle_show_tables().await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Show(command::ShowCommand::Ledgers) => {
+                self.handle_show_ledgers().await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Show(command::ShowCommand::Indexes { ref table }) => {
+                self.handle_show_indexes(table.as_deref()).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Describe { ref table, sample } => {
+                self.handle_describe(table, sample).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::HistoryOf {
+                ref table,
+                ref document_id,
+                ref from,
+                ref to,
+            } => {
+                self.handle_history_of(table, document_id, from.as_deref(), to.as_deref())
+                    .await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Diff(ref diff) => {
+                self.handle_diff(diff).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Verify {
+                ref table,
+                ref document_id,
+            } => {
+                self.handle_verify(table, document_id).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Hash => {
+                self.handle_hash()?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Export {
+                ref source,
+                ref path,
+                format,
+            } => {
+                self.handle_export(source, path, format).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Import(ref import) => {
+                self.handle_import(import).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Ledger(ledger) => {
+                self.handle_ledger_command(ledger).await?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Tx(command::TxCommand::Log) => {
+                self.handle_tx_log()?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Tx(command::TxCommand::List) => {
+                self.handle_tx_list()?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Tx(command::TxCommand::Use { ref name }) => {
+                self.handle_tx_use(name)?;
+                Ok(TickFlow::Again)
+            }
+            command::Backslash::Dryrun(ref on_off) => {
+                let dry_run: bool = on_off.into();
+                self.deps.env.update(|env| {
+                    env.config.ui.dry_run = dry_run;
+                    Ok(())
+                })?;
+                self.deps.ui.println(match dry_run {
+                    true => "dry run is on: transactions will be rolled back instead of committed, and ledgers won't be changed",
+                    false => "dry run is off: transactions will be committed",
+                });
+                Ok(TickFlow::Again)
+            }
         }
     }
 
@@ -260,6 +552,37 @@ where
         self.deps.ui.println(&format!("{}", self.deps.env));
     }
 
+    /// Prints the current value of every setting that can be changed with
+    /// `\set`.
+    pub(crate) fn handle_show_settings(&self) {
+        let config = self.deps.env.config();
+        let ui = &config.ui;
+        let settings = [
+            ("edit-mode", format!("{:?}", ui.edit_mode).to_lowercase()),
+            ("terminator-required", ui.terminator_required.to_string()),
+            ("format", format!("{:?}", ui.format).to_lowercase()),
+            ("auto-commit", ui.auto_commit.to_string()),
+            (
+                "display-query-metrics",
+                ui.display_query_metrics.to_string(),
+            ),
+            ("display-ctrl-signals", ui.display_ctrl_signals.to_string()),
+            ("verify-hashes", ui.verify_hashes.to_string()),
+            ("dry-run", ui.dry_run.to_string()),
+            (
+                "prompt",
+                format!(
+                    "{:?}",
+                    ui.prompt.as_deref().unwrap_or(prompt::DEFAULT_PROMPT)
+                ),
+            ),
+        ];
+
+        for (name, value) in settings.iter() {
+            self.deps.ui.println(&format!("{}: {}", name, value));
+        }
+    }
+
     pub(crate) async fn handle_show_tables(&self) -> Result<()> {
         let table_names = self.deps.driver.transact(|mut tx| async {
             let table_names =
@@ -276,6 +599,21 @@ where
         Ok(())
     }
 
+    /// Runs `statement` in its own transaction and returns the documents it
+    /// returned. Used by commands that need to look at data, rather than
+    /// display it.
+    pub(crate) async fn query(&self, statement: &str) -> Result<Vec<OwnedElement>> {
+        let results = self
+            .deps
+            .driver
+            .transact(|mut tx| async move {
+                let results = tx.execute_statement(statement).await?;
+                tx.commit(results).await
+            })
+            .await?;
+        results::elements(&results)
+    }
+
     pub(crate) async fn handle_status(&self) -> Result<()> {
         // TODO: Return latency information from recent commands if we're able to capture it.
         self.deps.ui.println(&format!(
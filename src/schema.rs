@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use amazon_qldb_driver::QldbSession;
+use anyhow::Result;
+use comfy_table::Table;
+use ion_rs::value::owned::{OwnedElement, OwnedStruct};
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Sequence, Struct, SymbolToken};
+use ion_rs::IonType;
+
+use crate::error;
+use crate::export::PagedQuery;
+use crate::runner::Runner;
+
+/// A table, as described by `information_schema.user_tables`.
+struct TableInfo {
+    name: String,
+    id: String,
+    status: String,
+    indexes: Vec<IndexInfo>,
+}
+
+struct IndexInfo {
+    id: String,
+    expr: String,
+    status: String,
+    /// Why building the index failed, if it did.
+    message: Option<String>,
+}
+
+fn string_field(strukt: &OwnedStruct, name: &str) -> String {
+    strukt
+        .get(name)
+        .and_then(|elem| elem.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+impl TableInfo {
+    fn from_element(elem: &OwnedElement) -> Option<TableInfo> {
+        let table = elem.as_struct()?;
+        let indexes = match table.get("indexes").and_then(|i| i.as_sequence()) {
+            Some(indexes) => indexes
+                .iter()
+                .filter_map(|index| index.as_struct())
+                .map(|index| IndexInfo {
+                    id: string_field(index, "indexId"),
+                    expr: string_field(index, "expr"),
+                    status: string_field(index, "status"),
+                    message: index
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .map(|m| m.to_string()),
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        Some(TableInfo {
+            name: string_field(table, "name"),
+            id: string_field(table, "tableId"),
+            status: string_field(table, "status"),
+            indexes,
+        })
+    }
+}
+
+/// Quotes `value` as a PartiQL string literal.
+pub(crate) fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Quotes `name` as a PartiQL identifier, so that table names are matched
+/// exactly (and can't be used to inject PartiQL).
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn ion_type_name(elem: &OwnedElement) -> &'static str {
+    if elem.is_null() {
+        return "null";
+    }
+    match elem.ion_type() {
+        IonType::Null => "null",
+        IonType::Boolean => "bool",
+        IonType::Integer => "int",
+        IonType::Float => "float",
+        IonType::Decimal => "decimal",
+        IonType::Timestamp => "timestamp",
+        IonType::Symbol => "symbol",
+        IonType::String => "string",
+        IonType::Clob => "clob",
+        IonType::Blob => "blob",
+        IonType::List => "list",
+        IonType::SExpression => "sexp",
+        IonType::Struct => "struct",
+    }
+}
+
+/// For every field path (e.g. `Owner.Name`) found in `docs`, the types it
+/// was seen with and the number of documents it was in. Sorted by path.
+fn summarize_fields(docs: &[OwnedElement]) -> Vec<(String, Vec<&'static str>, usize)> {
+    fn visit(
+        prefix: &str,
+        elem: &OwnedElement,
+        seen: &mut BTreeSet<String>,
+        fields: &mut BTreeMap<String, (BTreeSet<&'static str>, usize)>,
+    ) {
+        let strukt = match elem.as_struct() {
+            Some(strukt) if !elem.is_null() => strukt,
+            _ => return,
+        };
+        for (name, value) in strukt.iter() {
+            let path = match name.text() {
+                Some(name) if prefix.is_empty() => name.to_string(),
+                Some(name) => format!("{}.{}", prefix, name),
+                None => continue,
+            };
+            let entry = fields.entry(path.clone()).or_default();
+            entry.0.insert(ion_type_name(value));
+            // Fields repeated in one struct only count once.
+            if seen.insert(path.clone()) {
+                entry.1 += 1;
+            }
+            visit(&path, value, seen, fields);
+        }
+    }
+
+    let mut fields = BTreeMap::new();
+    for doc in docs {
+        visit("", doc, &mut BTreeSet::new(), &mut fields);
+    }
+    fields
+        .into_iter()
+        .map(|(path, (types, count))| (path, types.into_iter().collect(), count))
+        .collect()
+}
+
+/// Adds the documents of a page to `sample` until it has `size` documents.
+/// Returns whether it has, so that no more pages need to be read.
+fn add_to_sample(
+    sample: &mut Vec<OwnedElement>,
+    values: Vec<Vec<u8>>,
+    size: usize,
+) -> Result<bool> {
+    for value in values.into_iter().take(size.saturating_sub(sample.len())) {
+        sample.push(element_reader().read_one(&value)?);
+    }
+    Ok(sample.len() >= size)
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Reads (up to) `size` documents of `table`. QLDB has no `LIMIT`, so
+    /// pages are fetched until there are enough and the rest of the table is
+    /// never read.
+    pub(crate) async fn sample_documents(
+        &self,
+        table: &str,
+        size: usize,
+    ) -> Result<Vec<OwnedElement>> {
+        let ledger = self.deps.env.current_ledger().name.clone();
+        let statement = format!("SELECT * FROM {}", quote_identifier(table));
+        let mut query = PagedQuery::start(&self.deps.client, &ledger, &statement).await?;
+        let mut sample = vec![];
+        let read = async {
+            while let Some(values) = query.next_page().await? {
+                if add_to_sample(&mut sample, values, size)? {
+                    break;
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        query.finish().await;
+        read?;
+        Ok(sample)
+    }
+
+    async fn user_tables(&self, name: Option<&str>) -> Result<Vec<TableInfo>> {
+        let statement = match name {
+            Some(name) => format!(
+                "SELECT * FROM information_schema.user_tables WHERE name = {}",
+                quote_string(name)
+            ),
+            None => {
+                "SELECT * FROM information_schema.user_tables WHERE status = 'ACTIVE'".to_string()
+            }
+        };
+        let mut tables: Vec<_> = self
+            .query(&statement)
+            .await?
+            .iter()
+            .filter_map(TableInfo::from_element)
+            .collect();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tables)
+    }
+
+    /// Describes `table`: its id, status and indexes. With `sample`, the
+    /// fields of (up to) that many documents are summarized too.
+    pub(crate) async fn handle_describe(&self, table: &str, sample: Option<usize>) -> Result<()> {
+        // Dropped tables keep their name, so prefer the active one.
+        let mut tables = self.user_tables(Some(table)).await?;
+        tables.sort_by_key(|t| t.status != "ACTIVE");
+        let info = match tables.into_iter().next() {
+            Some(info) => info,
+            None => Err(error::usage_error(format!("no table named '{}'", table)))?,
+        };
+
+        self.deps.ui.println(&format!("table: {}", info.name));
+        self.deps.ui.println(&format!("id: {}", info.id));
+        self.deps.ui.println(&format!("status: {}", info.status));
+        if info.indexes.is_empty() {
+            self.deps.ui.println("indexes: none");
+        } else {
+            self.deps.ui.println("indexes:");
+            let mut indexes = Table::new();
+            indexes.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+            indexes.set_header(vec!["id", "expr", "status", "message"]);
+            for index in &info.indexes {
+                indexes.add_row(vec![
+                    index.id.clone(),
+                    index.expr.clone(),
+                    index.status.clone(),
+                    index.message.clone().unwrap_or_default(),
+                ]);
+            }
+            self.deps.ui.println(&format!("{}", indexes));
+        }
+
+        if let Some(sample) = sample {
+            let docs = &self.sample_documents(&info.name, sample).await?;
+
+            self.deps
+                .ui
+                .println(&format!("fields (sampled from {} documents):", docs.len()));
+            let mut fields = Table::new();
+            fields.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+            fields.set_header(vec!["field", "types", "present in"]);
+            for (path, types, count) in summarize_fields(docs) {
+                fields.add_row(vec![
+                    path,
+                    types.join(", "),
+                    format!("{}/{}", count, docs.len()),
+                ]);
+            }
+            self.deps.ui.println(&format!("{}", fields));
+        }
+        Ok(())
+    }
+
+    /// Lists the indexes of `table`, or of every active table.
+    pub(crate) async fn handle_show_indexes(&self, table: Option<&str>) -> Result<()> {
+        let tables = self.user_tables(table).await?;
+        if let (Some(table), true) = (table, tables.is_empty()) {
+            Err(error::usage_error(format!("no table named '{}'", table)))?
+        }
+
+        let mut indexes = Table::new();
+        indexes.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        indexes.set_header(vec!["table", "id", "expr", "status", "message"]);
+        for info in &tables {
+            for index in &info.indexes {
+                indexes.add_row(vec![
+                    info.name.clone(),
+                    index.id.clone(),
+                    index.expr.clone(),
+                    index.status.clone(),
+                    index.message.clone().unwrap_or_default(),
+                ]);
+            }
+        }
+        self.deps.ui.println(&format!("{}", indexes));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        assert_eq!("'it''s'", quote_string("it's"));
+        assert_eq!(r#""a""b""#, quote_identifier(r#"a"b"#));
+    }
+
+    #[test]
+    fn stops_sampling_once_full() -> Result<()> {
+        let page = || vec![br#"{ VIN: "1" }"#.to_vec(); 2];
+        let mut sample = vec![];
+        assert!(!add_to_sample(&mut sample, page(), 3)?);
+        assert!(add_to_sample(&mut sample, page(), 3)?);
+        assert_eq!(3, sample.len());
+        assert!(add_to_sample(&mut vec![], vec![], 0)?);
+        Ok(())
+    }
+
+    #[test]
+    fn summarizes_fields() -> Result<()> {
+        let docs = element_reader().read_all(
+            br#"
+{ VIN: "1", Owner: { Name: "a" }, Year: 2020 }
+{ VIN: "2", Owner: null, Year: "2021" }
+"#,
+        )?;
+        assert_eq!(
+            vec![
+                ("Owner".to_string(), vec!["null", "struct"], 2),
+                ("Owner.Name".to_string(), vec!["string"], 1),
+                ("VIN".to_string(), vec!["string"], 2),
+                ("Year".to_string(), vec!["int", "string"], 2),
+            ],
+            summarize_fields(&docs)
+        );
+        Ok(())
+    }
+}
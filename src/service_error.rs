@@ -0,0 +1,279 @@
+use amazon_qldb_driver::aws_sdk_qldbsession::error::{SendCommandError, SendCommandErrorKind};
+use amazon_qldb_driver::aws_sdk_qldbsession::types::SdkError;
+use amazon_qldb_driver::QldbError;
+
+/// A short, human-friendly description of an error returned by QLDB.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Explanation {
+    /// What happened, in a few words. Includes the exception name so that it
+    /// can be searched for.
+    pub(crate) summary: String,
+    /// What the user could do about it.
+    pub(crate) suggestion: String,
+    /// Whether the transaction the error happened in can still be used.
+    pub(crate) transaction_usable: bool,
+    /// Where in the statement QLDB found a syntax error, if it said.
+    pub(crate) position: Option<Position>,
+}
+
+/// A 1-based line and column in a statement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Position {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+pub(crate) fn service_error(e: &anyhow::Error) -> Option<&SendCommandError> {
+    match e.downcast_ref::<QldbError>() {
+        Some(QldbError::SdkError(SdkError::ServiceError { err, .. })) => Some(err),
+        _ => None,
+    }
+}
+
+/// Returns the kind of QLDB service error `e` is, if it is one.
+pub(crate) fn service_error_kind(e: &anyhow::Error) -> Option<&SendCommandErrorKind> {
+    service_error(e).map(|err| &err.kind)
+}
+
+/// Looks up `e` in the catalog of QLDB errors the shell knows how to explain.
+pub(crate) fn explain(e: &anyhow::Error) -> Option<Explanation> {
+    let err = service_error(e)?;
+    explain_kind(&err.kind, err.code(), err.message().unwrap_or_default())
+}
+
+/// `code` is only needed for errors the SDK doesn't model, such as access
+/// being denied.
+fn explain_kind(
+    kind: &SendCommandErrorKind,
+    code: Option<&str>,
+    message: &str,
+) -> Option<Explanation> {
+    let (summary, suggestion, transaction_usable) = match kind {
+        SendCommandErrorKind::OccConflictException(_) => (
+            "OCC conflict (OccConflictException): another transaction changed the same \
+            documents first",
+            "run '\\retry' to replay the transaction, or use --retry-on-conflict for scripts",
+            false,
+        ),
+        SendCommandErrorKind::BadRequestException(_) if is_expired(message) => (
+            "transaction expired (BadRequestException)",
+            "start a new transaction and commit it within 30 seconds",
+            false,
+        ),
+        SendCommandErrorKind::BadRequestException(_) => (
+            "QLDB rejected the statement (BadRequestException)",
+            "check the statement's PartiQL syntax and that the tables it uses exist \
+            (try '\\show tables')",
+            true,
+        ),
+        SendCommandErrorKind::LimitExceededException(_) => (
+            "a QLDB limit was exceeded (LimitExceededException)",
+            "split the work into smaller transactions (e.g. with --batch-size), see \
+            https://docs.aws.amazon.com/qldb/latest/developerguide/limits.html",
+            false,
+        ),
+        SendCommandErrorKind::CapacityExceededException(_) => (
+            "the ledger is over capacity (CapacityExceededException)",
+            "wait a moment and try again, or send fewer requests at once",
+            true,
+        ),
+        SendCommandErrorKind::RateExceededException(_) => (
+            "requests are being throttled (RateExceededException)",
+            "wait a moment and try again, or send fewer requests at once",
+            true,
+        ),
+        SendCommandErrorKind::InvalidSessionException(_) => (
+            "the session is no longer valid (InvalidSessionException)",
+            "a new session has been started, run the statement again",
+            false,
+        ),
+        _ if code == Some("AccessDeniedException") => (
+            "access denied (AccessDeniedException)",
+            "check that your credentials (see '\\env') allow qldb:SendCommand and the \
+            qldb:PartiQL* actions on this ledger",
+            false,
+        ),
+        _ => return None,
+    };
+
+    Some(Explanation {
+        summary: format!("{}: {}", summary, message),
+        suggestion: suggestion.to_string(),
+        transaction_usable,
+        position: syntax_position(message),
+    })
+}
+
+/// Renders `explanation` for the user. If QLDB said where the syntax error
+/// was, the offending line of `statement` is shown with a marker under it.
+pub(crate) fn render(explanation: &Explanation, statement: Option<&str>) -> String {
+    let mut rendered = explanation.summary.clone();
+    if let (Some(position), Some(statement)) = (explanation.position, statement) {
+        if let Some(marker) = mark_position(statement, position) {
+            rendered.push('\n');
+            rendered.push_str(&marker);
+        }
+    }
+    rendered.push_str(&format!("\nhint: {}", explanation.suggestion));
+    rendered
+}
+
+fn is_expired(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("has expired") || message.contains("no open transaction")
+}
+
+/// QLDB reports syntax errors like "Parser Error: at line 1, column 15:
+/// Unexpected token".
+fn syntax_position(message: &str) -> Option<Position> {
+    let rest = &message[message.find("line ")? + "line ".len()..];
+    let (line, rest) = leading_number(rest)?;
+    let rest = rest.trim_start_matches(',').trim_start();
+    let rest = rest.strip_prefix("column ")?;
+    let (column, _) = leading_number(rest)?;
+    Some(Position { line, column })
+}
+
+fn leading_number(input: &str) -> Option<(usize, &str)> {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    Some((input[..end].parse().ok()?, &input[end..]))
+}
+
+fn mark_position(statement: &str, position: Position) -> Option<String> {
+    let line = statement.lines().nth(position.line.checked_sub(1)?)?;
+    let column = position.column.checked_sub(1)?;
+    if column > line.chars().count() {
+        return None;
+    }
+    Some(format!("  {}\n  {}^", line, " ".repeat(column)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amazon_qldb_driver::aws_sdk_qldbsession::error::{
+        BadRequestException, CapacityExceededException, InvalidSessionException,
+        LimitExceededException, OccConflictException, RateExceededException,
+    };
+
+    fn rendered(kind: SendCommandErrorKind, code: Option<&str>, message: &str) -> Option<String> {
+        let explanation = explain_kind(&kind, code, message)?;
+        Some(render(&explanation, Some("select * fron foo")))
+    }
+
+    #[test]
+    fn explains_each_kind() {
+        let cases = vec![
+            (
+                SendCommandErrorKind::OccConflictException(OccConflictException::builder().build()),
+                None,
+                "Optimistic concurrency control failure",
+                "OCC conflict (OccConflictException): another transaction changed the same \
+                documents first: Optimistic concurrency control failure\n\
+                hint: run '\\retry' to replay the transaction, or use --retry-on-conflict for \
+                scripts",
+            ),
+            (
+                SendCommandErrorKind::BadRequestException(BadRequestException::builder().build()),
+                None,
+                "Transaction 123 has expired",
+                "transaction expired (BadRequestException): Transaction 123 has expired\n\
+                hint: start a new transaction and commit it within 30 seconds",
+            ),
+            (
+                SendCommandErrorKind::BadRequestException(BadRequestException::builder().build()),
+                None,
+                "Parser Error: at line 1, column 10: Unexpected token",
+                "QLDB rejected the statement (BadRequestException): Parser Error: at line 1, \
+                column 10: Unexpected token\n  select * fron foo\n           ^\n\
+                hint: check the statement's PartiQL syntax and that the tables it uses exist \
+                (try '\\show tables')",
+            ),
+            (
+                SendCommandErrorKind::LimitExceededException(
+                    LimitExceededException::builder().build(),
+                ),
+                None,
+                "too many documents",
+                "a QLDB limit was exceeded (LimitExceededException): too many documents\n\
+                hint: split the work into smaller transactions (e.g. with --batch-size), see \
+                https://docs.aws.amazon.com/qldb/latest/developerguide/limits.html",
+            ),
+            (
+                SendCommandErrorKind::CapacityExceededException(
+                    CapacityExceededException::builder().build(),
+                ),
+                None,
+                "busy",
+                "the ledger is over capacity (CapacityExceededException): busy\n\
+                hint: wait a moment and try again, or send fewer requests at once",
+            ),
+            (
+                SendCommandErrorKind::RateExceededException(
+                    RateExceededException::builder().build(),
+                ),
+                None,
+                "slow down",
+                "requests are being throttled (RateExceededException): slow down\n\
+                hint: wait a moment and try again, or send fewer requests at once",
+            ),
+            (
+                SendCommandErrorKind::InvalidSessionException(
+                    InvalidSessionException::builder().build(),
+                ),
+                None,
+                "session expired",
+                "the session is no longer valid (InvalidSessionException): session expired\n\
+                hint: a new session has been started, run the statement again",
+            ),
+            (
+                SendCommandErrorKind::Unhandled(Box::new(std::fmt::Error)),
+                Some("AccessDeniedException"),
+                "not authorized",
+                "access denied (AccessDeniedException): not authorized\n\
+                hint: check that your credentials (see '\\env') allow qldb:SendCommand and the \
+                qldb:PartiQL* actions on this ledger",
+            ),
+        ];
+        for (kind, code, message, expected) in cases {
+            assert_eq!(Some(expected.to_string()), rendered(kind, code, message));
+        }
+
+        assert_eq!(
+            None,
+            rendered(
+                SendCommandErrorKind::Unhandled(Box::new(std::fmt::Error)),
+                Some("InternalFailure"),
+                "oops"
+            )
+        );
+    }
+
+    #[test]
+    fn syntax_positions() {
+        assert_eq!(
+            Some(Position {
+                line: 1,
+                column: 15
+            }),
+            syntax_position("Parser Error: at line 1, column 15: Unexpected token")
+        );
+        assert_eq!(None, syntax_position("Table not found"));
+        assert_eq!(None, syntax_position("at line one, column 2"));
+    }
+
+    #[test]
+    fn marks_position() {
+        let statement = "select *\nfron foo";
+        assert_eq!(
+            Some("  fron foo\n  ^".to_string()),
+            mark_position(statement, Position { line: 2, column: 1 })
+        );
+        assert_eq!(
+            None,
+            mark_position(statement, Position { line: 3, column: 1 })
+        );
+    }
+}
@@ -34,6 +34,11 @@ pub struct Opt {
     #[structopt(short = "-s", long = "--qldb-session-endpoint", parse(try_from_str = Url::try_from))]
     pub qldb_session_endpoint: Option<Url>,
 
+    /// The qldb (control plane) API endpoint to connect to, used to list and
+    /// manage ledgers.
+    #[structopt(long = "--qldb-endpoint", parse(try_from_str = Url::try_from))]
+    pub qldb_endpoint: Option<Url>,
+
     /// The location of your AWS credentials profile to use for authentication.
     /// By default, the shell will pick a default profile as described in the shell guide.
     #[structopt(short, long = "--profile")]
@@ -48,9 +53,101 @@ pub struct Opt {
     /// The output format of your query results. By default, the format is ion.
     #[structopt(short, long = "--format")]
     pub format: Option<FormatMode>,
+
+    /// Automatically replay a transaction's statements in a new transaction if
+    /// committing it fails because of an OCC conflict. Only use this for
+    /// scripts whose statements are safe to run again.
+    #[structopt(long = "--retry-on-conflict")]
+    pub retry_on_conflict: bool,
+
+    /// How many times to retry an autocommit statement that QLDB throttles
+    /// (with exponential backoff). 0 disables retries.
+    #[structopt(long = "--throttle-retries")]
+    pub throttle_retries: Option<u32>,
+
+    /// Run every statement as usual, but abort each transaction instead of
+    /// committing it. Useful to check what a script would change.
+    #[structopt(long = "--dry-run")]
+    pub dry_run: bool,
+
+    /// Run the whole script in a single transaction. If any statement fails,
+    /// nothing is committed.
+    #[structopt(long = "--single-transaction", conflicts_with = "batch-size")]
+    pub single_transaction: bool,
+
+    /// Group statements that are not in a transaction into transactions of
+    /// this many statements, rather than running each statement in its own
+    /// transaction. If a statement fails, its batch is rolled back and the
+    /// script stops.
+    #[structopt(long = "--batch-size")]
+    pub batch_size: Option<usize>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Subcommand>,
+}
+
+/// Commands that run once, instead of starting the shell.
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+    /// Export or verify offline verification bundles.
+    Proof(ProofCommand),
+    /// Read a journal export that was downloaded from S3. This doesn't talk
+    /// to AWS, so no credentials are needed.
+    Journal(JournalCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct JournalCommand {
+    /// The directory the export was downloaded to. Its `.ion` (or `.json`)
+    /// files are read, including those in subdirectories.
+    #[structopt(parse(from_os_str))]
+    pub dir: PathBuf,
+    #[structopt(subcommand)]
+    pub query: JournalQuery,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum JournalQuery {
+    /// Lists every block: its address, time, transaction and hash.
+    Blocks,
+    /// Lists every transaction with the statements it ran.
+    Transactions,
+    /// Shows a transaction: its block, statements and the revisions it
+    /// wrote, with their hashes checked.
+    Transaction { transaction_id: String },
+    /// Shows every revision of a document in the export, each as a diff
+    /// against the revision before it.
+    History { document_id: String },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ProofCommand {
+    /// Writes the latest revision of a document, a proof and the ledger's
+    /// digest to a bundle that can be verified offline.
+    Export {
+        table: String,
+        document_id: String,
+        #[structopt(parse(from_os_str))]
+        bundle: PathBuf,
+    },
+    /// Verifies a bundle written by `qldb proof export`. This doesn't talk to
+    /// AWS, so no credentials are needed.
+    Verify {
+        #[structopt(parse(from_os_str))]
+        bundle: PathBuf,
+        /// The ledger digest (base64), as you got it from QLDB yourself. The
+        /// bundle's digest must be this one. Without it, only the bundle's
+        /// consistency with itself is checked.
+        #[structopt(long)]
+        digest: Option<String>,
+        /// The digest's tip address, e.g. '{strandId: "...", sequenceNo: 42}'.
+        /// The bundle's digest must end at this block.
+        #[structopt(long)]
+        digest_tip: Option<String>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, StructOpt)]
 #[serde(rename_all = "lowercase")]
 pub enum FormatMode {
     Ion,
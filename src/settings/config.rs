@@ -1,5 +1,6 @@
 use anyhow::Result;
 use dirs;
+use ion_rs::types::integer::IntAccess;
 use ion_rs::value::owned::OwnedStruct;
 use ion_rs::value::reader::{element_reader, ElementReader};
 use ion_rs::value::{Element, Sequence, Struct};
@@ -28,6 +29,9 @@ pub struct LedgerConfig {
     pub profile: Option<String>,
     pub region: Option<String>,
     pub qldb_session_endpoint: Option<String>,
+    /// The QLDB control plane endpoint, used for commands that find or
+    /// manage ledgers.
+    pub qldb_endpoint: Option<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -39,7 +43,19 @@ pub struct UiConfig {
     pub display_welcome: bool,
     pub display_ctrl_signals: bool,
     pub display_query_metrics: bool,
+    /// Check the hash of every revision a query returns, see `\hash`.
+    pub verify_hashes: bool,
     pub terminator_required: bool,
+    pub retry_on_conflict: bool,
+    /// How many times an autocommit statement is retried if QLDB throttles
+    /// it. 0 disables retries.
+    pub throttle_retries: u32,
+    /// Not configurable in the config file, see `--dry-run` and `\dryrun`.
+    pub dry_run: bool,
+    /// Not configurable in the config file, see `--single-transaction`.
+    pub single_transaction: bool,
+    /// Not configurable in the config file, see `--batch-size`.
+    pub batch_size: Option<usize>,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -129,7 +145,13 @@ impl Default for UiConfig {
             display_welcome: true,
             display_ctrl_signals: true,
             display_query_metrics: true,
+            verify_hashes: Default::default(),
             terminator_required: Default::default(),
+            retry_on_conflict: Default::default(),
+            throttle_retries: 5,
+            dry_run: Default::default(),
+            single_transaction: Default::default(),
+            batch_size: Default::default(),
             prompt: Default::default(),
             format: Default::default(),
             edit_mode: Default::default(),
@@ -189,12 +211,33 @@ impl TryFrom<&OwnedStruct> for UiConfig {
                 .ok_or(usage_error("`ui.display_query_metrics` should be a bool"))?
         }
 
+        if let Some(elem) = value.get("verify_hashes") {
+            ui.verify_hashes = elem
+                .as_bool()
+                .ok_or(usage_error("`ui.verify_hashes` should be a bool"))?
+        }
+
         if let Some(elem) = value.get("terminator_required") {
             ui.terminator_required = elem
                 .as_bool()
                 .ok_or(usage_error("`ui.terminator_required` should be a bool"))?
         }
 
+        if let Some(elem) = value.get("retry_on_conflict") {
+            ui.retry_on_conflict = elem
+                .as_bool()
+                .ok_or(usage_error("`ui.retry_on_conflict` should be a bool"))?
+        }
+
+        if let Some(elem) = value.get("throttle_retries") {
+            ui.throttle_retries =
+                elem.as_i64()
+                    .and_then(|i| u32::try_from(i).ok())
+                    .ok_or(usage_error(
+                        "`ui.throttle_retries` should be a non-negative int",
+                    ))?
+        }
+
         Ok(ui)
     }
 }
@@ -254,6 +297,14 @@ impl TryFrom<&OwnedStruct> for LedgerConfig {
             );
         }
 
+        if let Some(elem) = value.get("qldb_endpoint") {
+            ledger.qldb_endpoint = Some(
+                elem.as_str()
+                    .ok_or(usage_error("`ledger.qldb_endpoint` should be a string"))?
+                    .to_string(),
+            );
+        }
+
         Ok(ledger)
     }
 }
@@ -377,7 +428,7 @@ mod settings_config_tests {
     format: "table"
   },
   ledgers: [
-    { name: "my-ledger" }
+    { name: "my-ledger", qldb_endpoint: "http://localhost:8000" }
   ],
 }
 "#,
@@ -388,6 +439,10 @@ mod settings_config_tests {
         match ledgers.first() {
             Some(ledger) => {
                 assert_eq!("my-ledger", &ledger.name);
+                assert_eq!(
+                    Some("http://localhost:8000"),
+                    ledger.qldb_endpoint.as_deref()
+                );
             }
             None => panic!("config did not configure `my-ledger`"),
         };
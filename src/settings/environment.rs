@@ -1,9 +1,14 @@
 use super::{config::LedgerConfig, Opt};
-use crate::{awssdk_driver, error, settings::ShellConfig};
+use crate::{
+    awssdk_driver,
+    control_plane::{self, ControlPlane},
+    error,
+    settings::ShellConfig,
+};
 use anyhow::Result;
 use aws_sdk_qldbsession::Region;
 use std::{
-    fmt,
+    fmt, io,
     ops::{Deref, DerefMut},
     sync::{Arc, RwLock, RwLockReadGuard},
 };
@@ -26,8 +31,44 @@ impl Environment {
             config.ui.format = format;
         }
 
+        if cli.retry_on_conflict {
+            config.ui.retry_on_conflict = true;
+        }
+
+        if let Some(throttle_retries) = cli.throttle_retries {
+            config.ui.throttle_retries = throttle_retries;
+        }
+
+        if cli.dry_run {
+            config.ui.dry_run = true;
+        }
+
+        if cli.single_transaction {
+            config.ui.single_transaction = true;
+        }
+
+        if let Some(batch_size) = cli.batch_size {
+            if batch_size == 0 {
+                Err(error::usage_error("`--batch-size` must be at least 1"))?
+            }
+            config.ui.batch_size = Some(batch_size);
+        }
+
         // Next, identify the current ledger and region.
         let ledger_name = match (cli.ledger, &config.default_ledger) {
+            // Offer the user a choice, rather than sending them to the docs.
+            (None, None) if atty::is(atty::Stream::Stdin) => {
+                let region = awssdk_driver::determine_region(cli.region.as_ref()).await?;
+                let ledgers = ControlPlane::new(
+                    cli.profile.as_deref(),
+                    region,
+                    cli.qldb_endpoint.as_ref().map(|url| url.as_str()),
+                )
+                .await?
+                .list_ledgers()
+                .await?;
+                control_plane::pick_ledger(&ledgers, io::stdin().lock(), io::stdout())?
+            }
             (None, None) => Err(error::usage_error(
                 "`--ledger` was not specified and there is no `default_ledger` in your config",
             ))?,
@@ -40,6 +81,7 @@ impl Environment {
             profile: cli.profile,
             region: cli.region,
             qldb_session_endpoint: cli.qldb_session_endpoint.map(|url| url.to_string()),
+            qldb_endpoint: cli.qldb_endpoint.map(|url| url.to_string()),
         };
 
         let current_region =
@@ -121,6 +163,10 @@ impl EnvironmentInner {
                         preconfigured.qldb_session_endpoint.clone();
                 }
 
+                if current_ledger.qldb_endpoint.is_none() {
+                    current_ledger.qldb_endpoint = preconfigured.qldb_endpoint.clone();
+                }
+
                 self.current_region =
                     awssdk_driver::determine_region(current_ledger.region.as_ref()).await?;
 
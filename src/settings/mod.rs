@@ -1,4 +1,4 @@
-pub use command_line::{FormatMode, Opt};
+pub use command_line::{FormatMode, JournalCommand, JournalQuery, Opt, ProofCommand, Subcommand};
 pub use config::ShellConfig;
 pub use environment::Environment;
 
@@ -1,20 +1,38 @@
-use amazon_qldb_driver::aws_sdk_qldbsession::error::{SendCommandError, SendCommandErrorKind};
-use amazon_qldb_driver::aws_sdk_qldbsession::types::SdkError;
+use amazon_qldb_driver::aws_sdk_qldbsession::error::SendCommandErrorKind;
 use amazon_qldb_driver::{QldbDriver, QldbError, QldbSession, StatementResults};
 use anyhow::Result;
-use std::{sync::Arc, time::Instant};
+use comfy_table::Table;
+use rand::Rng;
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
 use tokio::{
     sync::{
         mpsc::{channel, Receiver, Sender},
         Mutex,
     },
     task::{self, JoinHandle},
+    time,
 };
 
+use crate::awssdk_driver;
+use crate::completion;
+use crate::prompt::TransactionState;
+use crate::service_error::{self, service_error_kind};
 use crate::QldbShellError;
 use crate::{error, runner::Runner};
 use crate::{results, runner::TickFlow};
 
+/// How many times `--retry-on-conflict` replays a transaction before giving
+/// up.
+const MAX_CONFLICT_REPLAYS: usize = 3;
+
+/// The delay before the first retry of a throttled statement. Each further
+/// retry doubles it, up to [`THROTTLE_MAX_DELAY`].
+const THROTTLE_BASE_DELAY: Duration = Duration::from_millis(200);
+const THROTTLE_MAX_DELAY: Duration = Duration::from_secs(5);
+
 // `handle` is in an Option to allow for partial drops. In the happy case, you
 // might want to await it to get some typed result back. However, if the
 // transaction goes out of scope, we want to cancel it "quickly". By default,
@@ -23,7 +41,120 @@ use crate::{results, runner::TickFlow};
 pub(crate) struct ShellTransaction {
     input: Sender<TransactionRequest>,
     results: Receiver<Result<StatementResults, QldbError>>,
+    started_id: Receiver<String>,
     handle: Option<JoinHandle<Result<()>>>,
+    pub(crate) id: Option<String>,
+    /// Set with `begin as NAME`, so that the transaction can be switched to
+    /// with `\tx use NAME`.
+    pub(crate) name: Option<String>,
+    pub(crate) started: Instant,
+    /// Every statement sent to this transaction, in order. Used for `\tx log`
+    /// and to replay the transaction after an OCC conflict.
+    pub(crate) statements: Vec<StatementRecord>,
+    pub(crate) kind: TransactionKind,
+    warned_expiring: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TransactionKind {
+    /// Started by the user with `begin`.
+    Manual,
+    /// Started by the shell to run a single statement.
+    Autocommit,
+    /// Started by the shell to run a group of statements, see
+    /// `--single-transaction` and `--batch-size`.
+    Batch,
+}
+
+pub(crate) struct StatementRecord {
+    pub(crate) statement: String,
+    /// `None` if the statement failed.
+    pub(crate) metrics: Option<StatementMetrics>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StatementMetrics {
+    pub(crate) documents: usize,
+    pub(crate) read_ios: i64,
+    pub(crate) server_time_ms: i64,
+    pub(crate) total_time_ms: u64,
+}
+
+impl ShellTransaction {
+    pub(crate) fn state(&self) -> TransactionState {
+        TransactionState {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            started: self.started,
+            statement_count: self.statements.len(),
+            warned: self.warned_expiring.clone(),
+        }
+    }
+
+    /// How the user refers to this transaction: its name, or its id if it
+    /// wasn't given one.
+    pub(crate) fn label(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.id.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The statements that succeeded, in order. This is what `\retry` and
+    /// `--retry-on-conflict` replay: a statement that failed didn't change
+    /// anything, and would most likely fail again.
+    pub(crate) fn statement_texts(&self) -> Vec<String> {
+        succeeded(&self.statements)
+    }
+
+    /// The number of documents modified by statements in this transaction,
+    /// which QLDB limits to
+    /// [`MAX_DOCUMENTS_PER_TRANSACTION`](crate::batch::MAX_DOCUMENTS_PER_TRANSACTION).
+    pub(crate) fn documents_modified(&self) -> usize {
+        self.statements
+            .iter()
+            .filter(|record| is_dml(&record.statement))
+            .filter_map(|record| record.metrics.as_ref())
+            .map(|metrics| metrics.documents)
+            .sum()
+    }
+
+    /// Metrics accumulated over every statement that succeeded.
+    pub(crate) fn totals(&self) -> StatementMetrics {
+        let mut totals = StatementMetrics::default();
+        for metrics in self.statements.iter().filter_map(|r| r.metrics.as_ref()) {
+            totals.documents += metrics.documents;
+            totals.read_ios += metrics.read_ios;
+            totals.server_time_ms += metrics.server_time_ms;
+            totals.total_time_ms += metrics.total_time_ms;
+        }
+        totals
+    }
+
+    /// e.g. "3 statements, read-ios: 4, server-time: 10ms, total-time: 30ms"
+    fn summary(&self) -> String {
+        let totals = self.totals();
+        let noun = match self.statements.len() {
+            1 => "statement",
+            _ => "statements",
+        };
+        format!(
+            "{} {}, read-ios: {}, server-time: {}ms, total-time: {}ms",
+            self.statements.len(),
+            noun,
+            totals.read_ios,
+            totals.server_time_ms,
+            totals.total_time_ms
+        )
+    }
+}
+
+fn succeeded(statements: &[StatementRecord]) -> Vec<String> {
+    statements
+        .iter()
+        .filter(|record| record.metrics.is_some())
+        .map(|record| record.statement.clone())
+        .collect()
 }
 
 impl Drop for ShellTransaction {
@@ -34,6 +165,62 @@ impl Drop for ShellTransaction {
     }
 }
 
+/// Whether QLDB throttled the request, in which case it can simply be sent
+/// again after a delay.
+fn is_throttled(e: &anyhow::Error) -> bool {
+    matches!(
+        service_error_kind(e),
+        Some(SendCommandErrorKind::RateExceededException(_))
+            | Some(SendCommandErrorKind::CapacityExceededException(_))
+    )
+}
+
+/// Exponential backoff with jitter: somewhere between half and all of
+/// `THROTTLE_BASE_DELAY * 2^(attempt - 1)`, so that concurrent shells don't
+/// retry in lockstep.
+fn throttle_delay(attempt: u32) -> Duration {
+    let delay = THROTTLE_BASE_DELAY
+        .checked_mul(1 << attempt.saturating_sub(1).min(16))
+        .unwrap_or(THROTTLE_MAX_DELAY)
+        .min(THROTTLE_MAX_DELAY);
+    let millis = delay.as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+}
+
+fn is_session_lost(e: &anyhow::Error) -> bool {
+    matches!(
+        service_error_kind(e),
+        Some(SendCommandErrorKind::InvalidSessionException(_))
+    )
+}
+
+/// Statements that only read can be run again without changing the outcome.
+fn is_read_only(statement: &str) -> bool {
+    match statement.split_whitespace().next() {
+        Some(keyword) => keyword.eq_ignore_ascii_case("select"),
+        None => false,
+    }
+}
+
+/// Statements that modify documents return one document (the id) for every
+/// document they modified.
+fn is_dml(statement: &str) -> bool {
+    match statement.split_whitespace().next() {
+        Some(keyword) => matches!(
+            &keyword.to_lowercase()[..],
+            "insert" | "update" | "delete" | "from"
+        ),
+        None => false,
+    }
+}
+
+fn is_occ_conflict(e: &anyhow::Error) -> bool {
+    matches!(
+        service_error_kind(e),
+        Some(SendCommandErrorKind::OccConflictException(_))
+    )
+}
+
 #[derive(Debug)]
 enum TransactionRequest {
     ExecuteStatement(String),
@@ -47,12 +234,17 @@ where
 {
     let (input, recv) = channel(1);
     let (output, results) = channel(1);
+    let (started, started_id) = channel(1);
 
     let handle = task::spawn(async move {
         let recv = Arc::new(Mutex::new(recv));
 
         let outcome = driver
             .transact(|mut tx| async {
+                if started.send(tx.id.clone()).await.is_err() {
+                    panic!("started ch should never be closed");
+                }
+
                 loop {
                     let input = async {
                         let mut guard = recv.lock().await;
@@ -87,7 +279,14 @@ where
     ShellTransaction {
         input,
         results,
+        started_id,
         handle: Some(handle),
+        id: None,
+        name: None,
+        started: Instant::now(),
+        statements: vec![],
+        kind: TransactionKind::Manual,
+        warned_expiring: Default::default(),
     }
 }
 
@@ -103,7 +302,43 @@ where
                 Start a transaction with 'start transaction' or 'begin'"
             )))?;
         }
-        self.handle_start_transaction()?;
+        if let Some(batch_size) = self.batch_size() {
+            return self.handle_batched_partiql(line, batch_size).await;
+        }
+        let max_retries = self.deps.env.config().ui.throttle_retries;
+        let mut throttled = 0;
+        let mut recovered = false;
+        loop {
+            match self.run_autocommit(line).await {
+                // Transaction retries are disabled in the driver (see
+                // `build_driver`), but a throttled request was rejected
+                // outright, so the statement can simply be sent again.
+                Err(e) if is_throttled(&e) && throttled < max_retries => {
+                    throttled += 1;
+                    let delay = throttle_delay(throttled);
+                    self.deps.ui.eprintln(&format!(
+                        "throttled, retrying in {}ms ({}/{})",
+                        delay.as_millis(),
+                        throttled,
+                        max_retries
+                    ));
+                    time::sleep(delay).await;
+                }
+                // The session was lost (and has been replaced by now). Nothing
+                // can have been committed, so reads are safe to run again.
+                Err(e) if is_session_lost(&e) && is_read_only(line) && !recovered => {
+                    recovered = true;
+                    self.deps
+                        .ui
+                        .eprintln("session lost, running the statement again in a new session");
+                }
+                other => return other,
+            }
+        }
+    }
+
+    async fn run_autocommit(&mut self, line: &str) -> Result<TickFlow> {
+        self.start_transaction(TransactionKind::Autocommit).await?;
         if let Err(e) = self.handle_partiql(line).await {
             // If we got an error, the transaction might still be open if the
             // error was not fatal to the transaction. So, we should send an
@@ -115,18 +350,199 @@ where
         Ok(TickFlow::Again)
     }
 
-    pub(crate) fn handle_start_transaction(&mut self) -> Result<()> {
+    /// Warns (once per transaction) when the open transaction is about to hit
+    /// QLDB's transaction timeout. This covers time spent running commands;
+    /// while the user is typing, the UI warns on a timer instead.
+    pub(crate) fn warn_if_transaction_expiring(&self) {
+        if let Some(ref tx) = self.current_transaction {
+            if let Some(warning) = tx.state().expiry_warning() {
+                self.deps.ui.eprintln(&warning);
+            }
+        }
+    }
+
+    /// If the open transaction has outlived QLDB's transaction timeout, there
+    /// is no point sending anything else to it. It is discarded, and an error
+    /// explaining what happened is returned.
+    pub(crate) fn take_expired_transaction(&mut self) -> Option<QldbShellError> {
+        let expired = match self.current_transaction {
+            Some(ref tx) => tx.state().is_expired(),
+            None => false,
+        };
+        if !expired {
+            return None;
+        }
+
+        let tx = self.current_transaction.take()?;
+        Some(QldbShellError::TransactionExpired {
+            id: tx.id.clone().unwrap_or_default(),
+            age_secs: tx.state().age().as_secs(),
+        })
+    }
+
+    /// Starts a transaction. If it is given a `name`, any open transaction is
+    /// put aside (see `\tx use`) rather than being an error.
+    pub(crate) async fn handle_start_transaction(&mut self, name: Option<&str>) -> Result<()> {
+        if self.deps.env.config().ui.single_transaction {
+            return Err(QldbShellError::UsageError(
+                "Transactions can't be started with --single-transaction, \
+                the whole script already runs in one"
+                    .to_string(),
+            ))?;
+        }
+        self.commit_batch().await?;
+
+        let parked = match name {
+            Some(name) => {
+                if self.open_transactions().any(|tx| tx.label() == name) {
+                    return Err(QldbShellError::UsageError(format!(
+                        "There is already a transaction named '{}'",
+                        name
+                    )))?;
+                }
+                self.current_transaction.take()
+            }
+            None => None,
+        };
+        if let Err(e) = self.start_transaction(TransactionKind::Manual).await {
+            if let Some(tx) = parked {
+                self.current_transaction = Some(tx);
+            }
+            return Err(e);
+        }
+        if let Some(tx) = parked {
+            self.parked_transactions.push(tx);
+        }
+
+        if let Some(ref mut tx) = self.current_transaction {
+            tx.name = name.map(|name| name.to_string());
+            match tx.name {
+                Some(ref name) => self.deps.ui.println(&format!(
+                    "transaction {} started as '{}'",
+                    tx.id.as_deref().unwrap_or_default(),
+                    name
+                )),
+                None => self.deps.ui.println(&format!(
+                    "transaction {} started",
+                    tx.id.as_deref().unwrap_or_default()
+                )),
+            }
+        }
+        Ok(())
+    }
+
+    /// The active transaction (if any) followed by the ones put aside.
+    fn open_transactions(&self) -> impl Iterator<Item = &ShellTransaction> {
+        self.current_transaction
+            .iter()
+            .chain(self.parked_transactions.iter())
+    }
+
+    /// Makes the transaction named (or with the id) `label` the active one.
+    /// The previously active transaction stays open.
+    pub(crate) fn handle_tx_use(&mut self, label: &str) -> Result<()> {
+        if let Some(ref tx) = self.current_transaction {
+            if tx.label() == label {
+                return Ok(());
+            }
+        }
+        let index = self
+            .parked_transactions
+            .iter()
+            .position(|tx| tx.label() == label)
+            .ok_or_else(|| {
+                QldbShellError::UsageError(format!(
+                    "No open transaction named '{}', see '\\tx list'",
+                    label
+                ))
+            })?;
+
+        let tx = self.parked_transactions.remove(index);
+        if let Some(previous) = self.current_transaction.replace(tx) {
+            self.parked_transactions.push(previous);
+        }
+        if let Some(ref tx) = self.current_transaction {
+            self.deps.ui.println(&format!(
+                "switched to transaction '{}' ({})",
+                tx.label(),
+                tx.id.as_deref().unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_tx_list(&self) -> Result<()> {
+        if self.open_transactions().next().is_none() {
+            return Err(QldbShellError::UsageError(
+                "No open transactions".to_string(),
+            ))?;
+        }
+
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        table.set_header(vec!["", "name", "id", "age", "statements"]);
+        for (i, tx) in self.open_transactions().enumerate() {
+            let active = match (i, &self.current_transaction) {
+                (0, Some(_)) => "*",
+                _ => "",
+            };
+            table.add_row(vec![
+                active.to_string(),
+                tx.name.clone().unwrap_or_default(),
+                tx.id.clone().unwrap_or_default(),
+                format!("{}s", tx.state().age().as_secs()),
+                tx.statements.len().to_string(),
+            ]);
+        }
+        self.deps.ui.println(&format!("{}", table));
+        Ok(())
+    }
+
+    pub(crate) async fn start_transaction(&mut self, kind: TransactionKind) -> Result<()> {
         if let Some(_) = self.current_transaction {
             return Err(QldbShellError::UsageError(format!(
                 "Transaction already open"
             )))?;
         }
 
-        let new_tx = new_transaction(self.deps.driver.clone());
+        let new_tx = match self.begin(kind).await {
+            Err(e) if is_session_lost(&e) => {
+                // The driver handed out a session QLDB had already expired.
+                self.recover_session().await?;
+                self.begin(kind).await?
+            }
+            other => other?,
+        };
         self.current_transaction.replace(new_tx);
         Ok(())
     }
 
+    async fn begin(&self, kind: TransactionKind) -> Result<ShellTransaction> {
+        let mut new_tx = new_transaction(self.deps.driver.clone());
+        new_tx.kind = kind;
+        match new_tx.started_id.recv().await {
+            Some(id) => new_tx.id = Some(id),
+            None => {
+                // The coroutine quit before the transaction started, so it
+                // holds the reason why.
+                if let Some(h) = new_tx.handle.take() {
+                    h.await??;
+                }
+                Err(error::bug("transaction ended before it was started"))?
+            }
+        }
+        Ok(new_tx)
+    }
+
+    /// Replaces the driver (and with it, every pooled session) after QLDB
+    /// reports that a session is no longer valid. Without this, later
+    /// commands could be handed the same dead session.
+    async fn recover_session(&mut self) -> Result<()> {
+        let ledger = self.deps.driver.ledger_name().to_string();
+        self.deps.driver = awssdk_driver::build_driver(self.deps.client.clone(), ledger).await?;
+        Ok(())
+    }
+
     pub(crate) async fn handle_partiql(&mut self, line: &str) -> Result<TickFlow> {
         let tx = self
             .current_transaction
@@ -134,6 +550,10 @@ where
             .ok_or(QldbShellError::UsageError(format!("No active transaction")))?;
 
         let start = Instant::now();
+        tx.statements.push(StatementRecord {
+            statement: line.to_string(),
+            metrics: None,
+        });
 
         tx.input
             .send(TransactionRequest::ExecuteStatement(line.to_string()))
@@ -142,20 +562,49 @@ where
             Some(Ok(r)) => r,
             Some(Err(e)) => {
                 // Some errors end the transaction, some are recoverable.
-                if let QldbError::SdkError(SdkError::ServiceError {
-                    err: SendCommandError { kind, .. },
-                    ..
-                }) = &e
-                {
-                    let broken = match kind {
-                        SendCommandErrorKind::InvalidSessionException(_) => true,
-                        _ => false,
-                    };
-                    if broken {
-                        let _ = self.current_transaction.take();
+                let e = anyhow::Error::from(e);
+                let explanation = match service_error::explain(&e) {
+                    Some(explanation) => explanation,
+                    None => return Err(e),
+                };
+                let (id, kind) = (tx.id.clone().unwrap_or_default(), tx.kind);
+                let lost = if is_session_lost(&e) {
+                    tx.statement_texts()
+                } else {
+                    vec![]
+                };
+                if !explanation.transaction_usable {
+                    let _ = self.current_transaction.take();
+                }
+                if is_session_lost(&e) {
+                    self.recover_session().await?;
+                }
+
+                let mut message = service_error::render(&explanation, Some(line));
+                // The shell cleans up the transactions it started itself.
+                if kind == TransactionKind::Manual {
+                    if explanation.transaction_usable {
+                        message.push_str(&format!(
+                            "\ntransaction {} is still open: carry on, or 'abort' it",
+                            id
+                        ));
+                    } else if !lost.is_empty() {
+                        message.push_str(&format!(
+                            "\ntransaction {} was lost with the session, these statements were \
+                            not committed:",
+                            id
+                        ));
+                        for (i, statement) in lost.iter().enumerate() {
+                            message.push_str(&format!("\n  {}. {}", i + 1, statement));
+                        }
+                    } else {
+                        message.push_str(&format!(
+                            "\ntransaction {} can no longer be used, nothing in it was committed",
+                            id
+                        ));
                     }
                 }
-                Err(e)?
+                Err(e.context(message))?
             }
             None => {
                 // If the results channel is closed, it means the coroutine has
@@ -170,16 +619,36 @@ where
             }
         };
 
+        let stats = results.execution_stats();
+        let server_time = stats.timing_information.processing_time_milliseconds;
+        let total_time = Instant::now().duration_since(start).as_millis();
+        if let Some(record) = self
+            .current_transaction
+            .as_mut()
+            .and_then(|tx| tx.statements.last_mut())
+        {
+            record.metrics = Some(StatementMetrics {
+                documents: results.len(),
+                read_ios: stats.io_usage.read_i_os as i64,
+                server_time_ms: server_time as i64,
+                total_time_ms: total_time as u64,
+            });
+        }
+
         results::display_results(&results, &self.deps.env.config().ui.format, &self.deps.ui);
+        if self.deps.env.config().ui.verify_hashes {
+            if let Ok(docs) = results::elements(&results) {
+                self.print_hash_checks(&docs)?;
+            }
+        }
+        self.learn_fields(line, &results).await;
+        self.remember_results(&results);
 
         if self.deps.env.config().ui.display_query_metrics {
             let noun = match results.len() {
                 1 => "document",
                 _ => "documents",
             };
-            let stats = results.execution_stats();
-            let server_time = stats.timing_information.processing_time_milliseconds;
-            let total_time = Instant::now().duration_since(start).as_millis();
             self.deps.ui.println(&format!(
                 "{} {} in bag (read-ios: {}, server-time: {}ms, total-time: {}ms)",
                 results.len(),
@@ -201,23 +670,266 @@ where
 
         tx.input.send(TransactionRequest::Abort).await?;
         if let Some(h) = tx.handle.take() {
-            h.await?
-        } else {
-            Ok(())
+            h.await??;
         }
+
+        if tx.kind != TransactionKind::Autocommit {
+            let mut summary = format!(
+                "transaction {} aborted",
+                tx.id.as_deref().unwrap_or_default()
+            );
+            if self.deps.env.config().ui.display_query_metrics {
+                summary.push_str(&format!(" ({})", tx.summary()));
+            }
+            self.deps.ui.println(&summary);
+        }
+        Ok(())
     }
 
+    /// Commits the current transaction.
+    ///
+    /// If the commit fails with an OCC conflict, the statements of the
+    /// transaction are remembered so that they can be replayed with `\retry`.
+    /// With `--retry-on-conflict`, they are replayed straight away instead.
     pub(crate) async fn handle_commit(&mut self) -> Result<()> {
+        let mut replays = 0;
+        loop {
+            let (id, statements, kind) = match self.current_transaction {
+                Some(ref tx) => (
+                    tx.id.clone().unwrap_or_default(),
+                    tx.statement_texts(),
+                    tx.kind,
+                ),
+                None => Err(QldbShellError::UsageError(
+                    "No active transaction".to_string(),
+                ))?,
+            };
+
+            let e = match self.commit().await {
+                Ok(()) => {
+                    // A dry run rolled the tables back, so the catalog is
+                    // still right.
+                    let dry_run = self.deps.env.config().ui.dry_run;
+                    if !dry_run && statements.iter().any(|s| completion::is_ddl(s)) {
+                        self.refresh_catalog().await;
+                    }
+                    return Ok(());
+                }
+                Err(e) if is_occ_conflict(&e) => e,
+                Err(e) => {
+                    if is_session_lost(&e) {
+                        self.recover_session().await?;
+                    }
+                    return Err(match service_error::explain(&e) {
+                        Some(explanation) => {
+                            let message = format!(
+                                "{}\ntransaction {} was not committed",
+                                service_error::render(&explanation, None),
+                                id
+                            );
+                            e.context(message)
+                        }
+                        None => e,
+                    });
+                }
+            };
+
+            if self.deps.env.config().ui.retry_on_conflict && replays < MAX_CONFLICT_REPLAYS {
+                replays += 1;
+                self.deps.ui.eprintln(&format!(
+                    "transaction {} had an OCC conflict, replaying {} statement(s) ({}/{})",
+                    id,
+                    statements.len(),
+                    replays,
+                    MAX_CONFLICT_REPLAYS
+                ));
+                self.replay(&statements, kind).await?;
+                continue;
+            }
+
+            match service_error::explain(&e) {
+                Some(explanation) => self
+                    .deps
+                    .ui
+                    .eprintln(&service_error::render(&explanation, None)),
+                None => self.deps.ui.eprintln(&format!("{}", e)),
+            }
+            self.conflicted_statements = Some(statements);
+            return Err(QldbShellError::OccConflict { id })?;
+        }
+    }
+
+    /// Replays the statements of the last transaction that failed to commit
+    /// because of an OCC conflict in a new transaction, and then commits it.
+    pub(crate) async fn handle_retry(&mut self) -> Result<()> {
+        if self.current_transaction.is_some() {
+            return Err(QldbShellError::UsageError(
+                "Transaction already open".to_string(),
+            ))?;
+        }
+        let statements = self.conflicted_statements.take().ok_or_else(|| {
+            QldbShellError::UsageError("There is no transaction to retry".to_string())
+        })?;
+
+        self.deps.ui.println("Replaying:");
+        for statement in &statements {
+            self.deps.ui.println(&format!("  {}", statement));
+        }
+
+        self.replay(&statements, TransactionKind::Manual).await?;
+        self.handle_commit().await
+    }
+
+    /// Runs `statements` in a new transaction, which is left open. If any of
+    /// them fail, the new transaction is aborted.
+    async fn replay(&mut self, statements: &[String], kind: TransactionKind) -> Result<()> {
+        self.start_transaction(kind).await?;
+        for statement in statements {
+            if let Err(e) = self.handle_partiql(statement).await {
+                let _ = self.handle_abort().await;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> Result<()> {
         let mut tx = self
             .current_transaction
             .take()
             .ok_or(QldbShellError::UsageError(format!("No active transaction")))?;
 
+        if self.deps.env.config().ui.dry_run {
+            tx.input.send(TransactionRequest::Abort).await?;
+            if let Some(h) = tx.handle.take() {
+                h.await??;
+            }
+
+            let mut summary = format!(
+                "DRY RUN — rolled back transaction {}",
+                tx.id.as_deref().unwrap_or_default()
+            );
+            if self.deps.env.config().ui.display_query_metrics {
+                summary.push_str(&format!(" ({})", tx.summary()));
+            }
+            self.deps.ui.println(&summary);
+            return Ok(());
+        }
+
         tx.input.send(TransactionRequest::Commit).await?;
         if let Some(h) = tx.handle.take() {
-            h.await?
+            h.await??;
         } else {
             Err(error::bug("transaction committed but there are no results"))?
         }
+
+        self.report_commit(&tx);
+        Ok(())
+    }
+
+    /// Prints the transaction id and commit digest (which can be used to find
+    /// the transaction in the journal). For autocommit transactions, this is
+    /// only logged, as it would otherwise follow every single statement.
+    fn report_commit(&self, tx: &ShellTransaction) {
+        let mut summary = format!(
+            "transaction {} committed",
+            tx.id.as_deref().unwrap_or_default()
+        );
+        if self.deps.env.config().ui.display_query_metrics {
+            summary.push_str(&format!(" ({})", tx.summary()));
+        }
+        let digest = match self
+            .deps
+            .commit_digests
+            .take(tx.id.as_deref().unwrap_or_default())
+        {
+            Some(digest) => format!("commit digest: {}", digest),
+            None => "commit digest: none returned".to_string(),
+        };
+
+        if tx.kind == TransactionKind::Autocommit {
+            self.deps.ui.debug(&summary);
+            self.deps.ui.debug(&digest);
+        } else {
+            self.deps.ui.println(&summary);
+            self.deps.ui.println(&digest);
+        }
+    }
+
+    /// Lists every statement run in the current transaction, along with its
+    /// metrics.
+    pub(crate) fn handle_tx_log(&self) -> Result<()> {
+        let tx = self
+            .current_transaction
+            .as_ref()
+            .ok_or_else(|| QldbShellError::UsageError("No active transaction".to_string()))?;
+
+        let mut table = Table::new();
+        table.load_preset(comfy_table::presets::ASCII_BORDERS_ONLY_CONDENSED);
+        table.set_header(vec![
+            "#",
+            "statement",
+            "documents",
+            "read-ios",
+            "server-time",
+            "total-time",
+        ]);
+        for (i, record) in tx.statements.iter().enumerate() {
+            let row = match record.metrics {
+                Some(ref m) => vec![
+                    (i + 1).to_string(),
+                    record.statement.clone(),
+                    m.documents.to_string(),
+                    m.read_ios.to_string(),
+                    format!("{}ms", m.server_time_ms),
+                    format!("{}ms", m.total_time_ms),
+                ],
+                None => vec![
+                    (i + 1).to_string(),
+                    record.statement.clone(),
+                    "failed".to_string(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ],
+            };
+            table.add_row(row);
+        }
+
+        self.deps.ui.println(&format!(
+            "transaction {} (open for {}s)",
+            tx.id.as_deref().unwrap_or_default(),
+            tx.state().age().as_secs()
+        ));
+        if !tx.statements.is_empty() {
+            self.deps.ui.println(&format!("{}", table));
+        }
+        self.deps.ui.println(&tx.summary());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_statements_are_not_replayed() {
+        let record = |statement: &str, ok: bool| StatementRecord {
+            statement: statement.to_string(),
+            metrics: match ok {
+                true => Some(StatementMetrics::default()),
+                false => None,
+            },
+        };
+        let statements = vec![
+            record("INSERT INTO Foo VALUE {'a': 1}", true),
+            record("SELECT * FROM Nope", false),
+            record("UPDATE Foo SET a = 2", true),
+        ];
+        assert_eq!(
+            vec!["INSERT INTO Foo VALUE {'a': 1}", "UPDATE Foo SET a = 2"],
+            succeeded(&statements)
+        );
     }
 }
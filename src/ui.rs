@@ -1,16 +1,23 @@
+use crate::completion::Catalog;
+use crate::prompt::Prompt;
 use crate::repl_helper::QldbHelper;
 use crate::settings::config::EditMode;
 use crate::{command::SetCommand, settings::Environment};
 use anyhow::Result;
 use dirs;
 use rustyline::{config::Builder, error::ReadlineError, Cmd, KeyCode, KeyEvent, Modifiers};
-use rustyline::{Config, Editor};
+use rustyline::{Config, Editor, ExternalPrinter};
 use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
 use std::{cell::RefCell, rc::Rc};
 use tracing::{debug, warn};
 
 pub(crate) trait Ui {
-    fn set_prompt(&self, prompt: String);
+    fn set_prompt(&self, prompt: Prompt);
+
+    /// Updates what tab completion knows about the ledger.
+    fn set_catalog(&self, catalog: Catalog);
 
     fn user_input(&self) -> Result<String>;
 
@@ -58,10 +65,12 @@ pub mod testing {
     }
 
     impl Ui for TestUi {
-        fn set_prompt(&self, prompt: String) {
-            self.inner.borrow_mut().prompt = prompt;
+        fn set_prompt(&self, prompt: Prompt) {
+            self.inner.borrow_mut().prompt = prompt.text;
         }
 
+        fn set_catalog(&self, _catalog: Catalog) {}
+
         fn user_input(&self) -> Result<String> {
             let mut inner = self.inner.borrow_mut();
             if inner.pending.is_empty() {
@@ -110,7 +119,8 @@ pub mod testing {
 struct UiInner {
     env: Environment,
     editor: Editor<QldbHelper>,
-    prompt: String,
+    prompt: Prompt,
+    catalog: Catalog,
     pending_actions: Vec<String>,
 }
 
@@ -140,7 +150,8 @@ impl ConsoleUi {
             inner: Rc::new(RefCell::new(UiInner {
                 env,
                 editor,
-                prompt: "> ".to_owned(),
+                prompt: Prompt::default(),
+                catalog: Catalog::default(),
                 pending_actions: vec![],
             })),
         }
@@ -163,6 +174,26 @@ fn create_editor(builder: Builder, env: Environment) -> Editor<QldbHelper> {
     editor
 }
 
+/// Warns that the open transaction is about to expire while the user is still
+/// typing, rather than after they press enter. The warning is printed above
+/// the prompt. Dropping the returned sender cancels it.
+fn expiry_timer(editor: &mut Editor<QldbHelper>, prompt: &Prompt) -> Option<Sender<()>> {
+    let tx = prompt.state.transaction.clone()?;
+    if tx.is_expired() {
+        return None;
+    }
+    let mut printer = editor.create_external_printer().ok()?;
+    let (cancel, cancelled) = mpsc::channel();
+    thread::spawn(move || {
+        if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(tx.until_expiring()) {
+            if let Some(warning) = tx.expiry_warning() {
+                let _ = printer.print(warning);
+            }
+        }
+    });
+    Some(cancel)
+}
+
 #[cfg(not(windows))]
 fn force_newline_event_seq() -> KeyEvent {
     KeyEvent(KeyCode::Enter, Modifiers::ALT)
@@ -176,10 +207,14 @@ fn force_newline_event_seq() -> KeyEvent {
 }
 
 impl Ui for ConsoleUi {
-    fn set_prompt(&self, prompt: String) {
+    fn set_prompt(&self, prompt: Prompt) {
         self.inner.borrow_mut().prompt = prompt;
     }
 
+    fn set_catalog(&self, catalog: Catalog) {
+        self.inner.borrow_mut().catalog = catalog;
+    }
+
     /// Prompts the user for input or returns the next pending action.
     ///
     /// Users can enter multiple commands like 'foo; bar'. These commands will
@@ -198,7 +233,15 @@ impl Ui for ConsoleUi {
         }
 
         let prompt = inner.prompt.clone();
-        match inner.editor.readline(&prompt) {
+        let catalog = inner.catalog.clone();
+        if let Some(helper) = inner.editor.helper_mut() {
+            helper.set_prompt(prompt.clone());
+            helper.set_catalog(catalog);
+        }
+        let timer = expiry_timer(&mut inner.editor, &prompt);
+        let line = inner.editor.readline(&prompt.text);
+        drop(timer);
+        match line {
             Ok(line) => {
                 let line = line.trim();
                 if !line.is_empty() {
@@ -250,6 +293,8 @@ impl Ui for ConsoleUi {
                 let editor = create_editor(create_config(&inner.env), inner.env.clone());
                 inner.editor = editor;
             }
+            // Everything else is read from the environment as it is needed.
+            _ => {}
         }
 
         Ok(())
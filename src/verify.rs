@@ -0,0 +1,305 @@
+use amazon_qldb_driver::QldbSession;
+use anyhow::{anyhow, Result};
+use ion_rs::types::integer::IntAccess;
+use ion_rs::value::owned::OwnedElement;
+use ion_rs::value::reader::{element_reader, ElementReader};
+use ion_rs::value::{Element, Sequence, Struct};
+
+use crate::control_plane::{ControlPlane, LedgerDigest};
+use crate::error;
+use crate::history::is_table_name;
+use crate::qldb_hash::QldbHash;
+use crate::results;
+use crate::runner::Runner;
+use crate::schema::quote_string;
+
+/// Renders a block address (`{strandId: "...", sequenceNo: 42}`) as the Ion
+/// text `GetRevision` expects.
+pub(crate) fn block_address_text(address: &OwnedElement) -> Option<String> {
+    let address = address.as_struct()?;
+    let strand_id = address.get("strandId")?.as_str()?;
+    let sequence_no = address.get("sequenceNo")?.as_i64()?;
+    let strand_id = results::ion_text(&OwnedElement::from(strand_id.to_string())).ok()?;
+    Some(format!(
+        "{{strandId: {}, sequenceNo: {}}}",
+        strand_id, sequence_no
+    ))
+}
+
+/// The `hash` field of a revision, as stored by QLDB.
+pub(crate) fn stored_hash(revision: &OwnedElement) -> Option<QldbHash> {
+    let hash = revision.as_struct()?.get("hash")?.as_bytes()?;
+    Some(QldbHash::from_bytes(hash.to_vec()))
+}
+
+/// Checks that `revision` (as returned by `GetRevision`) is the revision with
+/// `hash`: that it stores that hash, and that its data and metadata hash to
+/// it. The proof only proves the hash, not what the revision contains.
+pub(crate) fn check_revision(revision: &OwnedElement, hash: &QldbHash) -> Result<()> {
+    if stored_hash(revision).as_ref() != Some(hash) {
+        Err(anyhow!(
+            "FAIL: the revision QLDB returned has a different hash than the committed view"
+        ))?
+    }
+    match QldbHash::of_revision(revision)? {
+        Some(ref computed) if computed == hash => Ok(()),
+        Some(computed) => Err(anyhow!(
+            "FAIL: the revision's data and metadata hash to {}, not to its hash {}",
+            computed,
+            hash
+        )),
+        None => Err(anyhow!(
+            "FAIL: the revision QLDB returned has no metadata to hash"
+        )),
+    }
+}
+
+/// Parses the proof returned by `GetRevision`: an Ion list of hashes.
+pub(crate) fn parse_proof(text: &str) -> Result<Vec<QldbHash>> {
+    proof_hashes(&element_reader().read_one(text.as_bytes())?)
+}
+
+pub(crate) fn proof_hashes(proof: &OwnedElement) -> Result<Vec<QldbHash>> {
+    let hashes = match proof.as_sequence() {
+        Some(hashes) => hashes,
+        None => Err(anyhow!("the proof is not a list of hashes"))?,
+    };
+    hashes
+        .iter()
+        .map(|hash| match hash.as_bytes() {
+            Some(bytes) => Ok(QldbHash::from_bytes(bytes.to_vec())),
+            None => Err(anyhow!("the proof is not a list of hashes")),
+        })
+        .collect()
+}
+
+/// Checks that `hash` (of a revision), combined with `proof`, leads to
+/// `digest`.
+pub(crate) fn check_proof(hash: &QldbHash, proof: &[QldbHash], digest: &QldbHash) -> Result<()> {
+    let computed = hash.with_proof(proof);
+    if &computed != digest {
+        Err(anyhow!(
+            "FAIL: the proof leads to {}, not to the digest",
+            computed
+        ))?
+    }
+    Ok(())
+}
+
+/// The latest revision of a document, proven to be in a digest.
+pub(crate) struct ProvenRevision {
+    pub(crate) ledger: String,
+    /// The block address of the revision, as Ion text.
+    pub(crate) address: String,
+    pub(crate) hash: QldbHash,
+    /// The revision as returned by `GetRevision`, as Ion text.
+    pub(crate) revision_text: String,
+    pub(crate) proof: Vec<QldbHash>,
+    /// The proof as returned by `GetRevision`, as Ion text.
+    pub(crate) proof_text: String,
+    pub(crate) digest: LedgerDigest,
+}
+
+impl<C> Runner<C>
+where
+    C: QldbSession + Send + Sync + Clone + 'static,
+{
+    /// Gets the latest revision of a document along with a proof that it is
+    /// in the current digest of the ledger.
+    pub(crate) async fn prove_revision(
+        &self,
+        table: &str,
+        document_id: &str,
+    ) -> Result<ProvenRevision> {
+        if !is_table_name(table) {
+            Err(error::usage_error(format!(
+                "'{}' is not a table name",
+                table
+            )))?
+        }
+        let statement = format!(
+            "SELECT r.blockAddress, r.hash FROM _ql_committed_{} AS r WHERE r.metadata.id = {}",
+            table,
+            quote_string(document_id)
+        );
+        let committed = match self.query(&statement).await?.into_iter().next() {
+            Some(committed) => committed,
+            None => Err(error::usage_error(format!(
+                "no document '{}' in table '{}'",
+                document_id, table
+            )))?,
+        };
+        let address = committed
+            .as_struct()
+            .and_then(|c| c.get("blockAddress"))
+            .and_then(block_address_text);
+        let (address, hash) = match (address, stored_hash(&committed)) {
+            (Some(address), Some(hash)) => (address, hash),
+            _ => Err(error::bug(
+                "the committed view returned a revision without a block address or hash",
+            ))?,
+        };
+
+        let ledger = self.deps.env.current_ledger().name.clone();
+        let control_plane = ControlPlane::from_env(&self.deps.env).await?;
+        let digest = control_plane.get_digest(&ledger).await?;
+        let proof = control_plane
+            .get_revision(&ledger, &address, document_id, &digest.tip_address)
+            .await?;
+
+        let revision = element_reader().read_one(proof.revision.as_bytes())?;
+        check_revision(&revision, &hash)?;
+        Ok(ProvenRevision {
+            ledger,
+            address,
+            hash,
+            revision_text: proof.revision,
+            proof: parse_proof(&proof.proof)?,
+            proof_text: proof.proof,
+            digest,
+        })
+    }
+
+    /// Verifies the latest revision of a document: that its hash, combined
+    /// with a proof from QLDB, leads to the current digest of the ledger.
+    pub(crate) async fn handle_verify(&self, table: &str, document_id: &str) -> Result<()> {
+        let proven = self.prove_revision(table, document_id).await?;
+
+        self.deps
+            .ui
+            .println(&format!("document: {} (table {})", document_id, table));
+        self.deps
+            .ui
+            .println(&format!("block address: {}", proven.address));
+        self.deps
+            .ui
+            .println(&format!("revision hash: {}", proven.hash));
+        self.deps.ui.println(&format!(
+            "digest: {} (tip: {})",
+            proven.digest.digest, proven.digest.tip_address
+        ));
+
+        check_proof(&proven.hash, &proven.proof, &proven.digest.digest)?;
+        self.deps
+            .ui
+            .println("PASS: the revision is proven to be in the ledger digest");
+        Ok(())
+    }
+
+    /// Recomputes the hash of every revision in `docs` (documents with
+    /// `metadata` and `hash` fields) and compares it with the stored hash.
+    /// Returns how many revisions were checked and how many didn't match.
+    pub(crate) fn print_hash_checks(&self, docs: &[OwnedElement]) -> Result<(usize, usize)> {
+        let (mut checked, mut mismatched) = (0, 0);
+        for doc in docs {
+            let (stored, computed) = match (stored_hash(doc), QldbHash::of_revision(doc)?) {
+                (Some(stored), Some(computed)) => (stored, computed),
+                _ => continue,
+            };
+            let metadata = doc.as_struct().and_then(|d| d.get("metadata"));
+            let field = |name: &str| {
+                metadata
+                    .and_then(|m| m.as_struct())
+                    .and_then(|m| m.get(name))
+                    .map(|value| results::format_element_for_cell(Some(value)))
+                    .transpose()
+                    .map(|value| value.unwrap_or_default())
+            };
+            let revision = format!("{} version {}", field("id")?, field("version")?);
+
+            checked += 1;
+            if stored == computed {
+                self.deps
+                    .ui
+                    .println(&format!("{}: hash OK ({})", revision, stored));
+            } else {
+                mismatched += 1;
+                self.deps.ui.println(&format!(
+                    "{}: hash MISMATCH (stored {}, computed {})",
+                    revision, stored, computed
+                ));
+            }
+        }
+        Ok((checked, mismatched))
+    }
+
+    pub(crate) fn handle_hash(&self) -> Result<()> {
+        let docs = match self.recent_results.last() {
+            Some(results) => results.elements()?,
+            None => Err(error::usage_error(
+                "there are no results to check, run a statement first",
+            ))?,
+        };
+        match self.print_hash_checks(&docs)? {
+            (0, _) => Err(error::usage_error(
+                "the last statement returned no revisions, select them from \
+                _ql_committed_TABLE or history(TABLE)",
+            ))?,
+            (checked, 0) => self
+                .deps
+                .ui
+                .println(&format!("PASS: {} revisions checked", checked)),
+            (checked, mismatched) => Err(anyhow!(
+                "FAIL: {} of {} revisions do not match their hash",
+                mismatched,
+                checked
+            ))?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_addresses() -> Result<()> {
+        let address = element_reader()
+            .read_one(br#"{ strandId: "JdxjkR9bSYB5jMHWcI464T", sequenceNo: 7 }"#)?;
+        assert_eq!(
+            Some(r#"{strandId: "JdxjkR9bSYB5jMHWcI464T", sequenceNo: 7}"#.to_string()),
+            block_address_text(&address)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn revisions_must_match_their_hash() -> Result<()> {
+        let revision = |vin: &str, hash: &QldbHash| {
+            element_reader().read_one(
+                format!(
+                    "{{ hash: {{{{{}}}}}, data: {{ VIN: \"{}\" }}, metadata: {{ id: \"a\", version: 0 }} }}",
+                    hash, vin
+                )
+                .as_bytes(),
+            )
+        };
+        let hash = QldbHash::of_revision(&revision("1", &QldbHash::default())?)?.unwrap();
+        check_revision(&revision("1", &hash)?, &hash)?;
+
+        // The stored hash is right, but the data was changed.
+        let tampered = check_revision(&revision("2", &hash)?, &hash);
+        assert!(tampered
+            .unwrap_err()
+            .to_string()
+            .contains("data and metadata hash to"));
+        // The stored hash isn't the one that was proven.
+        let other = QldbHash::from_bytes(vec![0, 1, 2]);
+        assert!(check_revision(&revision("1", &other)?, &hash).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn proofs() -> Result<()> {
+        assert_eq!(
+            vec![
+                QldbHash::from_bytes(vec![0, 1, 2]),
+                QldbHash::from_bytes(vec![3, 4, 5])
+            ],
+            parse_proof("[{{AAEC}}, {{AwQF}}]")?
+        );
+        assert!(parse_proof("[1]").is_err());
+        Ok(())
+    }
+}